@@ -0,0 +1,191 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Compares the memory used to deserialize a large `channels.list`
+// response by reading it into a `String` first (as `Slack` used to)
+// with that used by deserializing straight from the response stream
+// through a `BufReader` (as `Slack` does now). Both cases read the
+// same payload through the same `Read` implementation and differ only
+// in how the body is handed to serde_json.
+//
+// Peak memory is the growth of the peak resident set size while a case
+// runs, read from `/proc/self/status`, so this benchmark only reports
+// memory use on Linux. Each case runs in its own child process so that
+// memory kept by the allocator after one case isn't counted in another,
+// and reads the payload from a file written by the parent process so
+// that memory used to build the payload isn't counted either.
+//
+// The payload is the synthetic `channels.list` fixture cassette, with
+// its channels repeated until the body is a few megabytes long.
+//
+// Run with `cargo bench`.
+
+extern crate serde_json;
+extern crate slacker;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use slacker::ChannelsListResponse;
+
+static CASSETTE: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/cassettes/channels_list.json");
+static CASE_ARGUMENT: &'static str = "--case";
+const CHANNELS: usize = 10000;
+const ITERATIONS: u32 = 10;
+
+// Ways of deserializing a response body, with a description of each.
+static CASES: &'static [(&'static str, &'static str)] = &[("string", "read into string, then parse"),
+                                                          ("stream", "stream through BufReader")];
+
+// Reader over the payload that hands it out in small chunks,
+// like a response body read from a socket.
+struct ChunkedReader<'a> {
+    payload: &'a [u8],
+}
+
+impl<'a> Read for ChunkedReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = if buf.len() < 4096 { buf.len() } else { 4096 };
+        self.payload.read(&mut buf[..len])
+    }
+}
+
+// `channels.list` response body with `CHANNELS` channels,
+// copied from the channels in the fixture's response.
+fn large_payload() -> Vec<u8> {
+    let mut cassette = String::new();
+    File::open(CASSETTE).and_then(|mut f| f.read_to_string(&mut cassette)).unwrap();
+    let cassette: Value = serde_json::from_str(&cassette).unwrap();
+    let body = cassette.as_array().unwrap()[0].lookup("response.body").and_then(|b| b.as_str()).unwrap();
+    let mut body: Value = serde_json::from_str(body).unwrap();
+
+    let recorded = body.find("channels").and_then(|c| c.as_array()).unwrap().clone();
+    let channels = (0..CHANNELS)
+        .map(|i| {
+            let mut channel = recorded[i % recorded.len()].clone();
+            channel.as_object_mut().unwrap().insert("id".to_string(), Value::String(format!("C{:08}", i)));
+            channel
+        })
+        .collect();
+    body.as_object_mut().unwrap().insert("channels".to_string(), Value::Array(channels));
+    serde_json::to_vec(&body).unwrap()
+}
+
+fn deserialize(case: &str, payload: &[u8]) -> ChannelsListResponse {
+    let reader = ChunkedReader { payload: payload };
+    match case {
+        "string" => {
+            let mut body = String::new();
+            BufReader::new(reader).read_to_string(&mut body).unwrap();
+            serde_json::from_str(&body).unwrap()
+        }
+        "stream" => serde_json::from_reader(BufReader::new(reader)).unwrap(),
+        _ => panic!("unknown case {}", case),
+    }
+}
+
+// Value in KiB of the `field` line in `/proc/self/status`,
+// or `None` if it can't be read on this platform.
+fn proc_status_kib(field: &str) -> Option<u64> {
+    let mut status = String::new();
+    if File::open("/proc/self/status").and_then(|mut f| f.read_to_string(&mut status)).is_err() {
+        return None;
+    }
+    status.lines()
+        .find(|line| line.starts_with(field))
+        .and_then(|line| line[field.len()..].trim().trim_right_matches("kB").trim().parse().ok())
+}
+
+// Reset the peak resident set size of this process to its current
+// resident set size. Does nothing on platforms that don't support it.
+fn reset_peak_memory() {
+    let _ = File::create("/proc/self/clear_refs").and_then(|mut f| f.write_all(b"5"));
+}
+
+// Run `case` `ITERATIONS` times on the payload in `path` and print the
+// average time taken, in nanoseconds, and the growth of the peak resident
+// set size, in KiB, for the parent process to read.
+fn run_case(case: &str, path: &str) {
+    let mut file = File::open(path).unwrap();
+    let mut payload = Vec::with_capacity(file.metadata().unwrap().len() as usize);
+    file.read_to_end(&mut payload).unwrap();
+    reset_peak_memory();
+    let baseline = proc_status_kib("VmRSS:");
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        assert_eq!(deserialize(case, &payload).channels.len(), CHANNELS);
+    }
+    let elapsed = started.elapsed() / ITERATIONS;
+    let peak = match (baseline, proc_status_kib("VmHWM:")) {
+        (Some(baseline), Some(peak)) if peak > baseline => (peak - baseline).to_string(),
+        (Some(_), Some(_)) => "0".to_string(),
+        _ => "-".to_string(),
+    };
+    println!("{} {}", elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64, peak);
+}
+
+// Run `case` on the payload in `path` in a child process, returning
+// the average time taken and the growth of the peak memory, if it's known.
+fn measure(case: &str, path: &Path) -> (Duration, Option<u64>) {
+    let output = Command::new(env::current_exe().unwrap())
+        .arg(CASE_ARGUMENT)
+        .arg(case)
+        .arg(path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "case {} failed", case);
+    let output = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = output.trim().split(' ').collect();
+    let nanos: u64 = fields[0].parse().unwrap();
+    (Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32), fields[1].parse().ok())
+}
+
+fn report(name: &str, (duration, peak): (Duration, Option<u64>)) {
+    let peak = match peak {
+        Some(peak) => format!("{}KiB", peak),
+        None => "unknown".to_string(),
+    };
+    println!("{:<32} {:>8.1}ms {:>13}",
+             name,
+             duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0,
+             peak);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == CASE_ARGUMENT) {
+        run_case(&args[i + 1], &args[i + 2]);
+        io::stdout().flush().unwrap();
+        return;
+    }
+
+    let payload = large_payload();
+    let path = env::temp_dir().join("slacker-bench-channels-list.json");
+    File::create(&path).and_then(|mut f| f.write_all(&payload)).unwrap();
+
+    println!("channels.list response of {:.1}KiB with {} channels",
+             payload.len() as f64 / 1024.0,
+             CHANNELS);
+    println!("{:<32} {:>10} {:>13}", "", "time", "peak memory");
+    for &(case, name) in CASES {
+        report(name, measure(case, &path));
+    }
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,190 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures_cpupool::{CpuFuture, CpuPool};
+use serde_json::Value;
+
+use base_types::{ChannelId, Timestamp, UserId};
+use errors::*;
+use methods::SlackMethod;
+use serde_types::*;
+use slack::Slack;
+
+/// Future that resolves to the result of a Slack API method call.
+pub type SlackFuture<T> = CpuFuture<T, Error>;
+
+/// Futures-based wrapper around `Slack`.
+///
+/// Each method has the same name and arguments as its `Slack`
+/// equivalent, and returns a future that resolves to the same
+/// result. API calls are made by a `Slack` instance on a
+/// dedicated thread pool, so the two clients build requests
+/// and parse responses identically.
+///
+/// This is *not* an asynchronous HTTP client: hyper 0.9 only does
+/// blocking I/O, so each call still blocks a pool thread until its
+/// response has been read. It saves callers from managing their own
+/// thread pool, no more. At most as many calls as the pool has threads
+/// are in flight at once; the rest wait for a free thread. The default
+/// pool has one thread per CPU, which suits few concurrent calls; use
+/// `with_pool` and a larger `CpuPool` to make more calls at once.
+#[derive(Clone)]
+pub struct AsyncSlack {
+    slack: Arc<Slack>,
+    pool: CpuPool,
+}
+
+impl AsyncSlack {
+    /// Create an `AsyncSlack` instance that makes API calls with `access_token`
+    /// on a thread pool with one thread per CPU.
+    pub fn new(access_token: &str) -> AsyncSlack {
+        AsyncSlack::with_pool(Slack::new(access_token), CpuPool::new_num_cpus())
+    }
+
+    /// Create an `AsyncSlack` instance that makes API calls via `slack`
+    /// on a thread pool with one thread per CPU.
+    pub fn from_slack(slack: Slack) -> AsyncSlack {
+        AsyncSlack::with_pool(slack, CpuPool::new_num_cpus())
+    }
+
+    /// Create an `AsyncSlack` instance that makes API calls via `slack` on `pool`.
+    pub fn with_pool(slack: Slack, pool: CpuPool) -> AsyncSlack {
+        AsyncSlack {
+            slack: Arc::new(slack),
+            pool: pool,
+        }
+    }
+
+    //
+    // api
+    //
+
+    pub fn api_test(&self, arguments: HashMap<String, String>, error: Option<String>) -> SlackFuture<ApiTestResponse> {
+        self.spawn(move |slack| {
+            let arguments = arguments.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+            slack.api_test(arguments, error.as_ref().map(|e| e.as_str()))
+        })
+    }
+
+    //
+    // channels
+    //
+
+    pub fn channels_archive(&self, channel: ChannelId) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_archive(&channel))
+    }
+
+    pub fn channels_create(&self, channel_name: String) -> SlackFuture<Channel> {
+        self.spawn(move |slack| slack.channels_create(&channel_name))
+    }
+
+    pub fn channels_info(&self, channel: ChannelId) -> SlackFuture<Channel> {
+        self.spawn(move |slack| slack.channels_info(&channel))
+    }
+
+    pub fn channels_invite(&self, channel: ChannelId, user: UserId) -> SlackFuture<Channel> {
+        self.spawn(move |slack| slack.channels_invite(&channel, &user))
+    }
+
+    pub fn channels_join(&self, channel_name: String) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_join(&channel_name))
+    }
+
+    pub fn channels_leave(&self, channel: ChannelId) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_leave(&channel))
+    }
+
+    pub fn channels_list(&self, exclude_archived: bool) -> SlackFuture<Vec<Channel>> {
+        self.spawn(move |slack| slack.channels_list(exclude_archived))
+    }
+
+    pub fn channels_mark(&self, channel: ChannelId, timestamp: Timestamp) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_mark(&channel, &timestamp))
+    }
+
+    pub fn channels_rename(&self, channel: ChannelId, new_name: String) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_rename(&channel, &new_name))
+    }
+
+    pub fn channels_set_purpose(&self, channel: ChannelId, new_purpose: String) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_set_purpose(&channel, &new_purpose))
+    }
+
+    pub fn channels_set_topic(&self, channel: ChannelId, new_topic: String) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_set_topic(&channel, &new_topic))
+    }
+
+    pub fn channels_unarchive(&self, channel: ChannelId) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.channels_unarchive(&channel))
+    }
+
+    //
+    // chat
+    //
+
+    pub fn chat_delete(&self,
+                       channel: ChannelId,
+                       message_timestamp: Timestamp,
+                       as_user: Option<bool>)
+                       -> SlackFuture<()> {
+        self.spawn(move |slack| slack.chat_delete(&channel, &message_timestamp, as_user))
+    }
+
+    pub fn chat_me_message(&self, channel: ChannelId, message_text: String) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.chat_me_message(&channel, &message_text))
+    }
+
+    pub fn chat_post_message(&self, channel: ChannelId, message: Message) -> SlackFuture<()> {
+        self.spawn(move |slack| slack.chat_post_message(&channel, &message))
+    }
+
+    pub fn chat_post_message_idempotent(&self,
+                                        channel: ChannelId,
+                                        message: Message,
+                                        key: String)
+                                        -> SlackFuture<Timestamp> {
+        self.spawn(move |slack| slack.chat_post_message_idempotent(&channel, &message, &key))
+    }
+
+    //
+    // catch-all api
+    //
+
+    pub fn call<M>(&self, args: M::Args) -> SlackFuture<M::Response>
+        where M: SlackMethod + 'static,
+              M::Args: Sized + Send + 'static,
+              M::Response: Send + 'static
+    {
+        self.spawn(move |slack| slack.call::<M>(&args))
+    }
+
+    pub fn call_raw(&self, slack_method: String, arguments: Vec<(String, String)>) -> SlackFuture<Value> {
+        self.spawn(move |slack| {
+            let arguments: Vec<(&str, &str)> = arguments.iter().map(|&(ref k, ref v)| (k.as_str(), v.as_str())).collect();
+            slack.call_raw(&slack_method, &arguments)
+        })
+    }
+
+    // Run `call` against the blocking client on the thread pool.
+    fn spawn<F, T>(&self, call: F) -> SlackFuture<T>
+        where F: FnOnce(&Slack) -> Result<T> + Send + 'static,
+              T: Send + 'static
+    {
+        let slack = self.slack.clone();
+        self.pool.spawn_fn(move || call(&slack))
+    }
+}
@@ -12,23 +12,161 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use hyper;
+use std::fmt;
 use std::ops::Deref;
+use std::str::FromStr;
+
+use hyper;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use errors::*;
+
+// Define a newtype over `String` for a value for which `$valid`
+// returns `true`, and whose `FromStr` (and so `Deserialize`)
+// fails with `$error` otherwise.
+macro_rules! slack_string {
+    ($(#[$attr:meta])* pub struct $id:ident; valid: $valid:expr; error: $error:expr; visitor: $visitor:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $id(String);
+
+        impl $id {
+            /// Extract a string slice containing the entire value.
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl FromStr for $id {
+            type Err = Error;
+
+            fn from_str(id: &str) -> Result<$id> {
+                if $valid(id) {
+                    Ok($id(id.to_string()))
+                } else {
+                    Err($error.into())
+                }
+            }
+        }
+
+        impl fmt::Display for $id {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl AsRef<str> for $id {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Serialize for $id {
+            fn serialize<S>(&self, serializer: &mut S) -> ::std::result::Result<(), S::Error>
+                where S: Serializer
+            {
+                serializer.serialize_str(&self.0)
+            }
+        }
+
+        struct $visitor;
+
+        impl de::Visitor for $visitor {
+            type Value = $id;
+
+            fn visit_str<E>(&mut self, value: &str) -> ::std::result::Result<$id, E>
+                where E: de::Error
+            {
+                value.parse().map_err(|e: Error| E::invalid_value(&format!("{}: {}", e, value)))
+            }
+        }
+
+        impl Deserialize for $id {
+            fn deserialize<D>(deserializer: &mut D) -> ::std::result::Result<$id, D::Error>
+                where D: Deserializer
+            {
+                deserializer.deserialize_str($visitor)
+            }
+        }
+    }
+}
+
+slack_string! {
+    /// Unique Slack channel id, for example, `C024BE91L`.
+    /// (Note that this is *not* the channel's display name.)
+    ///
+    /// Ids of public channels start with `C`, of private channels
+    /// with `G` and of direct message channels with `D`. Parse one
+    /// with `str::parse`, which fails with `InvalidChannelId` if it
+    /// doesn't have one of those prefixes followed by uppercase
+    /// letters and digits.
+    pub struct ChannelId;
+    valid: |id| is_valid_id(id, &['C', 'D', 'G']);
+    error: ErrorKind::InvalidChannelId;
+    visitor: ChannelIdVisitor
+}
+
+slack_string! {
+    /// Unique Slack user id, for example, `U024BE7LH`.
+    /// (Note that this is *not* the user's display name.)
+    ///
+    /// Ids of users start with `U`, or with `W` for users of an
+    /// Enterprise Grid organization. Parse one with `str::parse`,
+    /// which fails with `InvalidUserId` if it doesn't have one of
+    /// those prefixes followed by uppercase letters and digits.
+    pub struct UserId;
+    valid: |id| is_valid_id(id, &['U', 'W']);
+    error: ErrorKind::InvalidUserId;
+    visitor: UserIdVisitor
+}
 
-/// Type alias for a unique Slack channel id.
-/// (Note that this is *not* the channel's display name.)
-pub type ChannelId = String;
+slack_string! {
+    /// Timestamp of a Slack message, for example, `1503435956.000247`,
+    /// which identifies the message within its channel.
+    ///
+    /// Slack sends timestamps as strings, and they must be passed back
+    /// exactly as received, so they aren't converted to numbers. Parse
+    /// one with `str::parse`, which fails with `InvalidTimestamp` if it
+    /// isn't digits, a `.` and more digits.
+    pub struct Timestamp;
+    valid: is_valid_timestamp;
+    error: ErrorKind::InvalidTimestamp;
+    visitor: TimestampVisitor
+}
+
+fn is_valid_id(id: &str, prefixes: &[char]) -> bool {
+    let mut chars = id.chars();
+    match chars.next() {
+        Some(prefix) if prefixes.contains(&prefix) => {
+            id.len() > 1 && chars.all(|c| (c >= 'A' && c <= 'Z') || is_digit(c))
+        }
+        _ => false,
+    }
+}
 
-/// Type alias for a unique Slack user id.
-/// (Note that this is *not* the user's display name.)
-pub type UserId = String;
+fn is_valid_timestamp(ts: &str) -> bool {
+    let mut parts = ts.splitn(2, '.');
+    match (parts.next(), parts.next()) {
+        (Some(seconds), Some(sequence)) => {
+            !seconds.is_empty() && !sequence.is_empty() &&
+            seconds.chars().chain(sequence.chars()).all(is_digit)
+        }
+        _ => false,
+    }
+}
+
+// `true` if `c` is an ASCII digit. `char::is_digit` is used rather than
+// `char::is_ascii_digit`, which the crate's supported rustc doesn't have.
+fn is_digit(c: char) -> bool {
+    c.is_digit(10)
+}
 
 /// Wrapper over hyper::Url.
 ///
 /// Implementation note: Done because we can't implement
 /// a trait for a type where both the trait and type are
 /// defined in external crates.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SlackUrl(pub hyper::Url);
 
 impl Deref for SlackUrl {
@@ -37,3 +175,60 @@ impl Deref for SlackUrl {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    use errors::ErrorKind;
+
+    #[test]
+    fn parse_valid_ids() {
+        for id in &["C024BE91L", "D024BE91L", "G024BE91L"] {
+            assert_eq!(id.parse::<ChannelId>().unwrap().as_str(), *id);
+        }
+        assert_eq!("U024BE7LH".parse::<UserId>().unwrap().to_string(), "U024BE7LH");
+        assert_eq!("W024BE7LH".parse::<UserId>().unwrap().as_ref(), "W024BE7LH");
+    }
+
+    #[test]
+    fn reject_invalid_ids() {
+        for id in &["", "C", "U024BE91L", "c024be91l", "C024-BE91L", "#general"] {
+            match *id.parse::<ChannelId>().unwrap_err().kind() {
+                ErrorKind::InvalidChannelId => (),
+                ref kind => panic!("unexpected error {:?} for {}", kind, id),
+            }
+        }
+        for id in &["", "U", "C024BE7LH", "U024 BE7LH"] {
+            match *id.parse::<UserId>().unwrap_err().kind() {
+                ErrorKind::InvalidUserId => (),
+                ref kind => panic!("unexpected error {:?} for {}", kind, id),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!("1503435956.000247".parse::<Timestamp>().unwrap().as_str(), "1503435956.000247");
+        for ts in &["", "1503435956", "1503435956.", ".000247", "1503435956.000247.1", "1.5e9"] {
+            match *ts.parse::<Timestamp>().unwrap_err().kind() {
+                ErrorKind::InvalidTimestamp => (),
+                ref kind => panic!("unexpected error {:?} for {}", kind, ts),
+            }
+        }
+    }
+
+    #[test]
+    fn serialize_as_strings() {
+        let channel: ChannelId = serde_json::from_str(r#""C024BE91L""#).unwrap();
+        assert_eq!(serde_json::to_string(&channel).unwrap(), r#""C024BE91L""#);
+        assert!(serde_json::from_str::<ChannelId>(r#""U024BE7LH""#).is_err());
+        assert!(serde_json::from_str::<UserId>(r#""U024BE7LH""#).is_ok());
+
+        let ts: Timestamp = serde_json::from_str(r#""1503435956.000247""#).unwrap();
+        assert_eq!(serde_json::to_string(&ts).unwrap(), r#""1503435956.000247""#);
+        assert!(serde_json::from_str::<Timestamp>("1503435956.000247").is_err());
+    }
+}
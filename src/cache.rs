@@ -0,0 +1,236 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use base_types::ChannelId;
+use serde_types::Channel;
+
+static CHANNELS_INFO: &'static str = "channels.info";
+static CHANNELS_LIST: &'static str = "channels.list";
+
+/// Responses of directory lookups cached by `Slack`.
+///
+/// `channels.info` responses are cached by channel id and
+/// `channels.list` responses by their `exclude_archived` argument,
+/// each for the TTL configured for its method. Methods without
+/// a TTL aren't cached.
+///
+/// To keep a response that was fetched while a channel changed from
+/// being cached, take the cache's `generation` before making the API
+/// call and pass it when inserting the response. The response is only
+/// inserted if nothing was invalidated in the meantime.
+pub struct ResponseCache {
+    channels: TtlMap<ChannelId, Channel>,
+    channel_lists: TtlMap<bool, Vec<Channel>>,
+    // incremented whenever a response is invalidated
+    generation: Mutex<u64>,
+}
+
+// Map whose entries expire `ttl` after they're inserted.
+// Nothing is inserted if there's no `ttl`.
+struct TtlMap<K, V> {
+    ttl: Option<Duration>,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl ResponseCache {
+    /// Create a cache that keeps the responses of each method
+    /// in `ttls` (keyed by method name) for its TTL.
+    pub fn new(ttls: &HashMap<String, Duration>) -> ResponseCache {
+        ResponseCache {
+            channels: TtlMap::new(ttls.get(CHANNELS_INFO).cloned()),
+            channel_lists: TtlMap::new(ttls.get(CHANNELS_LIST).cloned()),
+            generation: Mutex::new(0),
+        }
+    }
+
+    /// Number of times responses have been invalidated.
+    pub fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap()
+    }
+
+    /// Cached `channels.info` response for `channel`, if any.
+    pub fn channel(&self, channel: &ChannelId) -> Option<Channel> {
+        self.channels.get(channel)
+    }
+
+    /// Cache the `channels.info` response for `channel`, requested
+    /// at `generation`, unless it has been invalidated since.
+    pub fn insert_channel(&self, channel: &ChannelId, info: &Channel, generation: u64) {
+        if *self.generation.lock().unwrap() == generation {
+            self.channels.insert(channel.clone(), info.clone());
+        }
+    }
+
+    /// Cached `channels.list` response, if any.
+    pub fn channel_list(&self, exclude_archived: bool) -> Option<Vec<Channel>> {
+        self.channel_lists.get(&exclude_archived)
+    }
+
+    /// Cache the `channels.list` response, requested at
+    /// `generation`, unless it has been invalidated since.
+    pub fn insert_channel_list(&self, exclude_archived: bool, channels: &[Channel], generation: u64) {
+        if *self.generation.lock().unwrap() == generation {
+            self.channel_lists.insert(exclude_archived, channels.to_vec());
+        }
+    }
+
+    /// Drop every cached response that includes `channel`.
+    pub fn invalidate_channel(&self, channel: &ChannelId) {
+        let mut generation = self.generation.lock().unwrap();
+        *generation += 1;
+        self.channels.remove(channel);
+        self.channel_lists.clear();
+    }
+
+    /// Drop every cached channel list, for example, because a channel was created.
+    pub fn invalidate_channel_lists(&self) {
+        let mut generation = self.generation.lock().unwrap();
+        *generation += 1;
+        self.channel_lists.clear();
+    }
+
+    /// Drop every cached response.
+    pub fn clear(&self) {
+        let mut generation = self.generation.lock().unwrap();
+        *generation += 1;
+        self.channels.clear();
+        self.channel_lists.clear();
+    }
+}
+
+impl<K, V> TtlMap<K, V>
+    where K: Eq + Hash,
+          V: Clone
+{
+    fn new(ttl: Option<Duration>) -> TtlMap<K, V> {
+        TtlMap {
+            ttl: ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return None,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        let expired = match entries.get(key) {
+            Some(&(inserted, ref value)) if inserted.elapsed() < ttl => return Some(value.clone()),
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            entries.remove(key);
+        }
+        None
+    }
+
+    fn insert(&self, key: K, value: V) {
+        if self.ttl.is_some() {
+            self.entries.lock().unwrap().insert(key, (Instant::now(), value));
+        }
+    }
+
+    fn remove(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_types::Channel;
+
+    fn channel(id: &str) -> Channel {
+        Channel {
+            id: id.parse().unwrap(),
+            name: "general".to_string(),
+            created: 0,
+            creator: "U1234567".parse().unwrap(),
+            is_archived: false,
+            is_general: true,
+            members: None,
+            topic: None,
+            purpose: None,
+            is_member: None,
+            last_read: None,
+            unread_count: None,
+            unread_count_display: None,
+        }
+    }
+
+    fn ttls(ttls: &[(&str, u64)]) -> HashMap<String, Duration> {
+        ttls.iter().map(|&(method, millis)| (method.to_string(), Duration::from_millis(millis))).collect()
+    }
+
+    #[test]
+    fn expire_entries_after_ttl() {
+        let cache = ResponseCache::new(&ttls(&[("channels.info", 20)]));
+        cache.insert_channel(&"C1".parse().unwrap(), &channel("C1"), cache.generation());
+        assert!(cache.channel(&"C1".parse().unwrap()).is_some());
+
+        thread::sleep(Duration::from_millis(40));
+        assert!(cache.channel(&"C1".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn cache_only_methods_with_ttl() {
+        let cache = ResponseCache::new(&ttls(&[("channels.list", 60000)]));
+        cache.insert_channel(&"C1".parse().unwrap(), &channel("C1"), cache.generation());
+        cache.insert_channel_list(true, &[channel("C1")], cache.generation());
+
+        assert!(cache.channel(&"C1".parse().unwrap()).is_none());
+        assert_eq!(cache.channel_list(true).unwrap().len(), 1);
+        assert!(cache.channel_list(false).is_none());
+    }
+
+    #[test]
+    fn invalidate_lists_with_channel() {
+        let cache = ResponseCache::new(&ttls(&[("channels.info", 60000), ("channels.list", 60000)]));
+        cache.insert_channel(&"C1".parse().unwrap(), &channel("C1"), cache.generation());
+        cache.insert_channel(&"C2".parse().unwrap(), &channel("C2"), cache.generation());
+        cache.insert_channel_list(true, &[channel("C1"), channel("C2")], cache.generation());
+
+        cache.invalidate_channel(&"C1".parse().unwrap());
+        assert!(cache.channel(&"C1".parse().unwrap()).is_none());
+        assert!(cache.channel(&"C2".parse().unwrap()).is_some());
+        assert!(cache.channel_list(true).is_none());
+    }
+
+    #[test]
+    fn skip_responses_requested_before_invalidation() {
+        let cache = ResponseCache::new(&ttls(&[("channels.info", 60000), ("channels.list", 60000)]));
+        let generation = cache.generation();
+        cache.invalidate_channel(&"C1".parse().unwrap());
+
+        cache.insert_channel(&"C1".parse().unwrap(), &channel("C1"), generation);
+        cache.insert_channel_list(true, &[channel("C1")], generation);
+        assert!(cache.channel(&"C1".parse().unwrap()).is_none());
+        assert!(cache.channel_list(true).is_none());
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use hyper::header::{ContentType, Headers};
+use hyper::mime::{Mime, SubLevel, TopLevel};
+use hyper::status::StatusCode;
+use serde_json::{self, Map, Value};
+use url::form_urlencoded;
+
+use auth;
+use errors::*;
+use transport::{HttpRequest, HttpResponse, Transport};
+
+static SET_COOKIE_HEADER: &'static str = "set-cookie";
+
+/// `Transport` that records request/response pairs to a
+/// file (a "cassette") and replays them later, so that code
+/// using `Slack` can be tested without a network connection.
+///
+/// Access tokens and other secrets are scrubbed from
+/// requests and responses before they're written to disk.
+///
+/// In replay mode each request must match the next recorded
+/// request (method, URL and arguments); otherwise the call fails
+/// with `ErrorKind::CassetteError`.
+pub struct CassetteTransport {
+    path: PathBuf,
+    recorder: Option<Box<Transport>>,
+    state: Mutex<CassetteState>,
+}
+
+struct CassetteState {
+    interactions: Vec<Interaction>,
+    next: usize,
+}
+
+struct Interaction {
+    method: String,
+    url: String,
+    request_body: Option<String>,
+    status: u16,
+    response_headers: Vec<(String, String)>,
+    response_body: String,
+}
+
+impl CassetteTransport {
+    /// Create a transport that makes requests via `transport` and
+    /// records them to the cassette at `path`, replacing its contents.
+    /// The cassette is written to disk after each request.
+    pub fn record<P, T>(path: P, transport: T) -> CassetteTransport
+        where P: AsRef<Path>,
+              T: Transport + 'static
+    {
+        CassetteTransport {
+            path: path.as_ref().to_path_buf(),
+            recorder: Some(Box::new(transport)),
+            state: Mutex::new(CassetteState {
+                interactions: Vec::new(),
+                next: 0,
+            }),
+        }
+    }
+
+    /// Create a transport that replays the requests recorded in the cassette at `path`.
+    pub fn replay<P>(path: P) -> Result<CassetteTransport> where P: AsRef<Path> {
+        let mut contents = String::new();
+        try!(File::open(path.as_ref()).and_then(|mut f| f.read_to_string(&mut contents)));
+        let interactions = try!(parse_cassette(&contents));
+        Ok(CassetteTransport {
+            path: path.as_ref().to_path_buf(),
+            recorder: None,
+            state: Mutex::new(CassetteState {
+                interactions: interactions,
+                next: 0,
+            }),
+        })
+    }
+
+    /// Returns `true` if every request recorded in the cassette has been replayed.
+    pub fn is_exhausted(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.next >= state.interactions.len()
+    }
+
+    fn record_interaction(&self, recorder: &Transport, request: &HttpRequest) -> Result<HttpResponse> {
+        let mut response = try!(recorder.send(request));
+        let mut body = Vec::new();
+        try!(response.body.read_to_end(&mut body));
+
+        let interaction = Interaction {
+            method: request.method.to_string(),
+            url: auth::redact_url(&request.url),
+            request_body: scrub_request_body(request),
+            status: response.status.to_u16(),
+            response_headers: response.headers
+                .iter()
+                .filter(|h| !h.name().eq_ignore_ascii_case(SET_COOKIE_HEADER))
+                .map(|h| (h.name().to_string(), h.value_string()))
+                .collect(),
+            response_body: scrub_json_body(&String::from_utf8_lossy(&body)),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.interactions.push(interaction);
+        try!(File::create(&self.path)
+            .and_then(|mut f| f.write_all(serialize_cassette(&state.interactions).as_bytes())));
+
+        Ok(HttpResponse {
+            status: response.status,
+            headers: response.headers,
+            body: Box::new(Cursor::new(body)),
+        })
+    }
+
+    fn replay_interaction(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.next;
+        let response = {
+            let interaction = match state.interactions.get(index) {
+                Some(interaction) => interaction,
+                None => return Err(cassette_error(format!("no recorded response for request {:?}", request))),
+            };
+
+            let method = request.method.to_string();
+            let url = auth::redact_url(&request.url);
+            let body = scrub_request_body(request);
+            if method != interaction.method || url != interaction.url ||
+               normalize_body(body.as_ref()) != normalize_body(interaction.request_body.as_ref()) {
+                return Err(cassette_error(format!("request {:?} does not match recorded request {} {} {:?}",
+                                                  request,
+                                                  interaction.method,
+                                                  interaction.url,
+                                                  interaction.request_body)));
+            }
+
+            let mut headers = Headers::new();
+            for &(ref name, ref value) in &interaction.response_headers {
+                headers.set_raw(name.clone(), vec![value.as_bytes().to_vec()]);
+            }
+            HttpResponse {
+                status: StatusCode::from_u16(interaction.status),
+                headers: headers,
+                body: Box::new(Cursor::new(interaction.response_body.clone().into_bytes())),
+            }
+        };
+        state.next += 1;
+        Ok(response)
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        match self.recorder {
+            Some(ref recorder) => self.record_interaction(&**recorder, request),
+            None => self.replay_interaction(request),
+        }
+    }
+}
+
+fn cassette_error(description: String) -> Error {
+    ErrorKind::CassetteError(description).into()
+}
+
+// Request body as text, with the values of secret arguments redacted.
+fn scrub_request_body(request: &HttpRequest) -> Option<String> {
+    request.body.as_ref().map(|body| {
+        match request.headers.get::<ContentType>() {
+            Some(&ContentType(Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, _))) => {
+                let pairs: Vec<(String, String)> = form_urlencoded::parse(body)
+                    .map(|(name, value)| (name.to_string(), auth::redact(&name, &value).to_string()))
+                    .collect();
+                form_urlencoded::Serializer::new(String::new()).extend_pairs(pairs).finish()
+            }
+            Some(&ContentType(Mime(TopLevel::Application, SubLevel::Json, _))) => {
+                scrub_json_body(&String::from_utf8_lossy(body))
+            }
+            _ => String::from_utf8_lossy(body).into_owned(),
+        }
+    })
+}
+
+// JSON body with the values of all secret fields redacted.
+fn scrub_json_body(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(value) => serde_json::to_string(&scrub_json(value)).unwrap_or_else(|_| body.to_string()),
+        Err(_) => body.to_string(),
+    }
+}
+
+fn scrub_json(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            Value::Object(object.into_iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::String(s) => Value::String(auth::redact(&name, &s).to_string()),
+                        v => scrub_json(v),
+                    };
+                    (name, value)
+                })
+                .collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(scrub_json).collect()),
+        v => v,
+    }
+}
+
+// Normalize a request body so that argument
+// order doesn't affect whether requests match.
+fn normalize_body(body: Option<&String>) -> Option<Value> {
+    body.map(|body| {
+        if let Ok(value) = serde_json::from_str::<Value>(body) {
+            return value;
+        }
+        let mut pairs: Vec<Value> = form_urlencoded::parse(body.as_bytes())
+            .map(|(name, value)| Value::String(format!("{}={}", name, value)))
+            .collect();
+        pairs.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+        Value::Array(pairs)
+    })
+}
+
+fn serialize_cassette(interactions: &[Interaction]) -> String {
+    let recorded: Vec<Value> = interactions.iter()
+        .map(|i| {
+            let mut request = Map::new();
+            request.insert("method".to_string(), Value::String(i.method.clone()));
+            request.insert("url".to_string(), Value::String(i.url.clone()));
+            if let Some(ref body) = i.request_body {
+                request.insert("body".to_string(), Value::String(body.clone()));
+            }
+
+            let mut headers = Map::new();
+            for &(ref name, ref value) in &i.response_headers {
+                headers.insert(name.clone(), Value::String(value.clone()));
+            }
+            let mut response = Map::new();
+            response.insert("status".to_string(), Value::U64(i.status as u64));
+            response.insert("headers".to_string(), Value::Object(headers));
+            response.insert("body".to_string(), Value::String(i.response_body.clone()));
+
+            let mut interaction = Map::new();
+            interaction.insert("request".to_string(), Value::Object(request));
+            interaction.insert("response".to_string(), Value::Object(response));
+            Value::Object(interaction)
+        })
+        .collect();
+    serde_json::to_string_pretty(&Value::Array(recorded)).expect("cassette serializes to json")
+}
+
+fn parse_cassette(contents: &str) -> Result<Vec<Interaction>> {
+    let recorded: Value = try!(serde_json::from_str(contents));
+    let recorded = try!(recorded.as_array().ok_or_else(|| cassette_error("cassette is not a json array".to_string())));
+
+    let mut interactions = Vec::with_capacity(recorded.len());
+    for (index, r) in recorded.iter().enumerate() {
+        let field = |path: &str| {
+            r.lookup(path).ok_or_else(|| cassette_error(format!("interaction {} is missing field '{}'", index, path)))
+        };
+        let string = |path: &str| {
+            field(path).and_then(|v| {
+                v.as_str().map(|s| s.to_string()).ok_or_else(|| {
+                    cassette_error(format!("interaction {} field '{}' is not a string", index, path))
+                })
+            })
+        };
+
+        let status = try!(field("response.status").and_then(|v| {
+            v.as_u64().ok_or_else(|| cassette_error(format!("interaction {} has an invalid status", index)))
+        }));
+        let response_headers = match r.lookup("response.headers").and_then(|h| h.as_object()) {
+            Some(headers) => {
+                headers.iter()
+                    .filter_map(|(name, value)| value.as_str().map(|v| (name.clone(), v.to_string())))
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        interactions.push(Interaction {
+            method: try!(string("request.method")),
+            url: try!(string("request.url")),
+            request_body: string("request.body").ok(),
+            status: status as u16,
+            response_headers: response_headers,
+            response_body: try!(string("response.body")),
+        });
+    }
+    Ok(interactions)
+}
@@ -13,10 +13,10 @@
 // limitations under the License.
 
 use serde::ser::{Serialize, Serializer};
-use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
 use serde_json;
+use serde_json::Value;
 use std::fmt;
-use url::form_urlencoded;
 
 use base_types::SlackUrl;
 use errors::Result as SlackResult;
@@ -46,6 +46,39 @@ impl fmt::Display for LinkNames {
     }
 }
 
+impl Serialize for LinkNames {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(self.simple_name())
+    }
+}
+
+struct LinkNamesVisitor;
+impl Visitor for LinkNamesVisitor {
+    type Value = LinkNames;
+
+    fn visit_str<E>(&mut self, value: &str) -> Result<LinkNames, E>
+        where E: de::Error
+    {
+        if value == LINK_NAMES_ENABLE {
+            Ok(LinkNames::Enable)
+        } else if value == LINK_NAMES_DISABLE {
+            Ok(LinkNames::Disable)
+        } else {
+            Err(E::invalid_value(&format!("unknown link_names: {}", value)))
+        }
+    }
+}
+
+impl Deserialize for LinkNames {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize_str(LinkNamesVisitor)
+    }
+}
+
 impl LinkNames {
     fn simple_name(&self) -> &str {
         match *self {
@@ -68,6 +101,39 @@ impl fmt::Display for MessageParseBehavior {
     }
 }
 
+impl Serialize for MessageParseBehavior {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(self.simple_name())
+    }
+}
+
+struct MessageParseBehaviorVisitor;
+impl Visitor for MessageParseBehaviorVisitor {
+    type Value = MessageParseBehavior;
+
+    fn visit_str<E>(&mut self, value: &str) -> Result<MessageParseBehavior, E>
+        where E: de::Error
+    {
+        if value == MESSAGE_PARSE_BEHAVIOR_FULL {
+            Ok(MessageParseBehavior::Full)
+        } else if value == MESSAGE_PARSE_BEHAVIOR_NONE {
+            Ok(MessageParseBehavior::None)
+        } else {
+            Err(E::invalid_value(&format!("unknown parse: {}", value)))
+        }
+    }
+}
+
+impl Deserialize for MessageParseBehavior {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize_str(MessageParseBehaviorVisitor)
+    }
+}
+
 impl MessageParseBehavior {
     fn simple_name(&self) -> &str {
         match *self {
@@ -91,6 +157,43 @@ impl fmt::Display for Color {
 
 }
 
+impl Serialize for Color {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(self.color_to_string())
+    }
+}
+
+// Named colors are deserialized into their variant
+// and every other color into `Color::Hex`.
+struct ColorVisitor;
+impl Visitor for ColorVisitor {
+    type Value = Color;
+
+    fn visit_str<E>(&mut self, value: &str) -> Result<Color, E>
+        where E: de::Error
+    {
+        if value == SLACK_COLOR_GOOD {
+            Ok(Color::Good)
+        } else if value == SLACK_COLOR_WARNING {
+            Ok(Color::Warning)
+        } else if value == SLACK_COLOR_DANGER {
+            Ok(Color::Danger)
+        } else {
+            Ok(Color::Hex(value.to_owned()))
+        }
+    }
+}
+
+impl Deserialize for Color {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize_str(ColorVisitor)
+    }
+}
+
 impl Color {
     fn color_to_string(&self) -> &str {
         match *self {
@@ -135,7 +238,7 @@ impl Deserialize for SlackUrl {
 
 macro_rules! encode_field {
     ($i:expr, $f:ident, $o:expr) => {
-        $i.$f.as_ref().map(|f| $o.append_pair(stringify!($f), &f.to_string()))
+        $i.$f.as_ref().map(|f| $o.push((stringify!($f), f.to_string())))
     };
 }
 
@@ -143,25 +246,22 @@ macro_rules! encode_field {
 /// to be sent to slack.
 /// NOTE: if you want your message to contain a literal '&', '>' or '<',
 /// entity-encode it before setting `Message.text`.
-pub fn encode_message(message: &Message) -> SlackResult<String> {
-    let mut encoded = form_urlencoded::Serializer::new(String::new());
+pub fn encode_message(message: &Message) -> SlackResult<Vec<(&'static str, String)>> {
+    let mut encoded = Vec::new();
 
+    try!(check_message_content(message));
     let has_text = message.text.is_some();
     let has_attachments = message.attachments.is_some();
 
-    if !has_text && !has_attachments {
-        return Err(ErrorKind::NoMessageContent.into());
-    }
-
     if has_text {
         let given_text = message.text.as_ref().unwrap();
         let encoded_text = try!(encode_message_text(&given_text));
-        encoded.append_pair("text", &encoded_text);
+        encoded.push(("text", encoded_text));
     }
     if has_attachments {
         let attachments = message.attachments.as_ref().unwrap();
         let serialized = try!(serde_json::to_string(attachments));
-        encoded.append_pair("attachments", &serialized);
+        encoded.push(("attachments", serialized));
     }
 
     encode_field!(message, parse, encoded);
@@ -173,10 +273,39 @@ pub fn encode_message(message: &Message) -> SlackResult<String> {
     encode_field!(message, icon_url, encoded);
     encode_field!(message, icon_emoji, encoded);
     encode_field!(message, mrkdwn, encoded);
+    if let Some(ref metadata) = message.metadata {
+        encoded.push(("metadata", try!(serde_json::to_string(metadata))));
+    }
 
-    let finished = encoded.finish();
-    print!("{:?}", finished);
-    Ok(finished)
+    Ok(encoded)
+}
+
+/// Encodes a `Message` struct, along with the `channel` to which
+/// it's posted, into a JSON object to be sent to slack.
+/// Unlike `encode_message`, nested structures such as attachments
+/// are serialized directly instead of into a JSON string.
+pub fn encode_message_json(channel: &str, message: &Message) -> SlackResult<String> {
+    try!(check_message_content(message));
+
+    let mut encoded = serde_json::to_value(message);
+    {
+        let object = encoded.as_object_mut().expect("message serializes to a json object");
+        object.insert("channel".to_owned(), Value::String(channel.to_owned()));
+        if let Some(ref given_text) = message.text {
+            let encoded_text = try!(encode_message_text(given_text));
+            object.insert("text".to_owned(), Value::String(encoded_text));
+        }
+    }
+
+    serde_json::to_string(&encoded).map_err(From::from)
+}
+
+fn check_message_content(message: &Message) -> SlackResult<()> {
+    if message.text.is_none() && message.attachments.is_none() {
+        Err(ErrorKind::NoMessageContent.into())
+    } else {
+        Ok(())
+    }
 }
 
 // TODO: only have to entity-encode `&`, `<`, `>`; applies to all text
@@ -185,3 +314,123 @@ pub fn encode_message(message: &Message) -> SlackResult<String> {
 fn encode_message_text(text: &str) -> SlackResult<String> {
     Ok(text.to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+
+    use serde_types::{Attachment, AttachmentFields, Color, LinkNames, Message, MessageMetadata, MessageParseBehavior};
+
+    // Convert form-encoded arguments into JSON values so
+    // that they can be compared with a JSON-encoded message.
+    // Attachments and metadata are form-encoded as JSON strings.
+    fn form_as_json(arguments: Vec<(&str, String)>) -> BTreeMap<String, Value> {
+        arguments.into_iter()
+            .map(|(name, value)| {
+                let value = if name == "attachments" || name == "metadata" {
+                    serde_json::from_str(&value).unwrap()
+                } else {
+                    Value::String(value)
+                };
+                (name.to_owned(), value)
+            })
+            .collect()
+    }
+
+    // Convert scalar JSON values into the strings that
+    // would have been sent for them in a form body.
+    fn json_as_form(encoded: &str) -> BTreeMap<String, Value> {
+        let value: Value = serde_json::from_str(encoded).unwrap();
+        value.as_object()
+            .unwrap()
+            .iter()
+            .map(|(name, value)| {
+                let value = match *value {
+                    Value::Bool(b) => Value::String(b.to_string()),
+                    ref v => v.clone(),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn form_and_json_encodings_carry_same_fields() {
+        let message = Message {
+            text: Some("this & < & > α 😁".to_owned()),
+            parse: Some(MessageParseBehavior::Full),
+            link_names: Some(LinkNames::Enable),
+            unfurl_links: Some(true),
+            username: Some("slacker".to_owned()),
+            as_user: Some(false),
+            icon_emoji: Some(":robot_face:".to_owned()),
+            mrkdwn: Some(false),
+            attachments: Some(vec![Attachment {
+                fallback: "fallback".to_owned(),
+                color: Some(Color::Hex("#36a64f".to_owned())),
+                title: Some("title".to_owned()),
+                fields: Some(vec![AttachmentFields {
+                    title: "field".to_owned(),
+                    value: "value".to_owned(),
+                    short: true,
+                }]),
+                ts: 123456789,
+                ..Default::default()
+            }]),
+            metadata: Some(MessageMetadata {
+                event_type: "task_created".to_owned(),
+                event_payload: vec![("id".to_owned(), Value::String("T1".to_owned()))].into_iter().collect(),
+            }),
+            ..Default::default()
+        };
+
+        let mut form = form_as_json(encode_message(&message).unwrap());
+        form.insert("channel".to_owned(), Value::String("C1234567".to_owned()));
+        let json = json_as_form(&encode_message_json("C1234567", &message).unwrap());
+
+        assert_eq!(form, json);
+    }
+
+    #[test]
+    fn json_encoding_serializes_attachments_natively() {
+        let message = Message {
+            attachments: Some(vec![Attachment {
+                fallback: "fallback".to_owned(),
+                color: Some(Color::Good),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        let encoded: Value = serde_json::from_str(&encode_message_json("C1234567", &message).unwrap()).unwrap();
+        let attachment = encoded.find("attachments").and_then(|a| a.as_array()).map(|a| &a[0]).unwrap();
+        assert_eq!(attachment.find("color"), Some(&Value::String("good".to_owned())));
+        assert_eq!(encoded.find("text"), None);
+    }
+
+    #[test]
+    fn deserialize_what_is_serialized() {
+        let parse = vec![MessageParseBehavior::Full, MessageParseBehavior::None];
+        let link_names = vec![LinkNames::Enable, LinkNames::Disable];
+        let colors = vec![Color::Good, Color::Warning, Color::Danger, Color::Hex("#36a64f".to_owned())];
+
+        let serialized = serde_json::to_string(&(&parse, &link_names, &colors)).unwrap();
+        assert_eq!(serialized,
+                   r##"[["full","none"],["1","0"],["good","warning","danger","#36a64f"]]"##);
+        let deserialized: (Vec<MessageParseBehavior>, Vec<LinkNames>, Vec<Color>) =
+            serde_json::from_str(&serialized).unwrap();
+        assert_eq!(serde_json::to_string(&deserialized).unwrap(), serialized);
+
+        assert!(serde_json::from_str::<LinkNames>(r#""Enable""#).is_err());
+    }
+
+    #[test]
+    fn reject_message_without_content() {
+        let message = Message::default();
+        assert!(encode_message(&message).is_err());
+        assert!(encode_message_json("C1234567", &message).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde_json::Value;
+
+/// Paths of the fields in `received` that aren't in `known`, for
+/// example, `channel.topic.is_locked`. Elements of an array share
+/// a path (`channels[].is_shared`), so each path is listed once.
+///
+/// `known` is `received` after it's been deserialized into the type
+/// it represents and serialized again, so it only has the fields that
+/// type has. Fields that are `null` are ignored, since types skip
+/// serializing some fields whose value is `None`.
+pub fn unknown_fields(received: &Value, known: &Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    collect_unknown_fields("", received, known, &mut unknown);
+    unknown
+}
+
+fn collect_unknown_fields(path: &str, received: &Value, known: &Value, unknown: &mut Vec<String>) {
+    match (received, known) {
+        (&Value::Object(ref received), &Value::Object(ref known)) => {
+            for (name, value) in received {
+                let field_path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                match known.get(name) {
+                    Some(known_value) => collect_unknown_fields(&field_path, value, known_value, unknown),
+                    None if value.is_null() || unknown.contains(&field_path) => (),
+                    None => unknown.push(field_path),
+                }
+            }
+        }
+        (&Value::Array(ref received), &Value::Array(ref known)) => {
+            let element_path = format!("{}[]", path);
+            for (value, known_value) in received.iter().zip(known) {
+                collect_unknown_fields(&element_path, value, known_value, unknown);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{self, Value};
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn report_nested_unknown_fields_once() {
+        let received = json(r#"{"ok": true, "is_new": 1, "unset": null, "channels": [
+            {"id": "C1", "topic": {"value": "a", "is_locked": true}},
+            {"id": "C2", "topic": {"value": "b", "is_locked": false}, "is_shared": false}
+        ]}"#);
+        let known = json(r#"{"ok": true, "channels": [
+            {"id": "C1", "topic": {"value": "a"}},
+            {"id": "C2", "topic": {"value": "b"}}
+        ]}"#);
+
+        assert_eq!(unknown_fields(&received, &known),
+                   vec!["channels[].topic.is_locked", "channels[].is_shared", "is_new"]);
+    }
+
+    #[test]
+    fn report_nothing_for_known_fields() {
+        let received = json(r#"{"ok": true, "channel": {"id": "C1"}}"#);
+        assert!(unknown_fields(&received, &received).is_empty());
+    }
+}
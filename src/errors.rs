@@ -15,6 +15,7 @@
 use hyper;
 use serde_json;
 use std::io;
+use std::time::Duration;
 use url;
 
 error_chain! {
@@ -113,6 +114,17 @@ error_chain! {
             description("slack api method failed with a timeout")
             display("slack api method call made using POST without content or with truncated content")
         }
+        /// Slack failed to complete the API call, likely because of a transient problem.
+        InternalError {
+            description("slack api method failed with an internal error")
+            display("slack api method call failed because of an internal server error")
+        }
+        /// Slack failed to complete the API call because of a catastrophic
+        /// error. Some part of the call may have taken effect.
+        FatalError {
+            description("slack api method failed with a fatal error")
+            display("slack api method call failed because of a fatal server error; it may have partially succeeded")
+        }
         /// A team setting prevents the user making the request from creating channels.
         ChannelActionRestricted {
             description("user cannot create channels")
@@ -229,10 +241,33 @@ error_chain! {
             description("slack message has too many attachments")
             display("cannot post message because it has too many attachments")
         }
-        /// Too many messages posted.
-        RateLimited {
-            description("rate-limited because too many messages posted")
-            display("cannot post message because message-posting has been rate-limited")
+        /// Too many API calls made. Contains the time the server asked
+        /// the client to wait before retrying, if it was specified.
+        RateLimited(retry_after: Option<Duration>) {
+            description("rate-limited because too many api calls made")
+            display("slack api method call rate-limited; retry after {:?}", retry_after)
+        }
+        /// Access token doesn't have any of the OAuth scopes the method
+        /// accepts. Contains the scopes the method accepts and the scopes
+        /// the token was granted, if known.
+        MissingScope(needed: Vec<String>, provided: Vec<String>) {
+            description("access token is missing the oauth scope required by the slack api method")
+            display("access token has scopes {:?} but slack api method needs one of {:?}", provided, needed)
+        }
+        /// Access token has expired and could not be refreshed.
+        TokenExpired {
+            description("access token expired")
+            display("access token expired and could not be refreshed")
+        }
+        /// Access token could not be refreshed.
+        TokenRefreshFailed(error_string: String) {
+            description("access token could not be refreshed")
+            display("access token could not be refreshed: '{}'", error_string)
+        }
+        /// Request could not be recorded to, or replayed from, a cassette.
+        CassetteError(description: String) {
+            description("cassette record or replay failed")
+            display("cassette record or replay failed: {}", description)
         }
         /// Unknown error returned from a slack API method call.
         Unknown(error_string: String) {
@@ -258,6 +293,8 @@ pub fn from_api_error_string(error_string: &String) -> Error {
         "invalid_post_type" => ErrorKind::InvalidPostType,
         "missing_post_type" => ErrorKind::MissingPostType,
         "request_timeout" => ErrorKind::RequestTimeout,
+        "internal_error" => ErrorKind::InternalError,
+        "fatal_error" => ErrorKind::FatalError,
         "restricted_action" => ErrorKind::ChannelActionRestricted,
         "no_channel" => ErrorKind::NoChannel,
         "name_taken" => ErrorKind::ChannelNameTaken,
@@ -281,9 +318,100 @@ pub fn from_api_error_string(error_string: &String) -> Error {
         "msg_too_long" => ErrorKind::MessageTooLong,
         "no_text" => ErrorKind::MessageHasNoText,
         "too_many_attachments" => ErrorKind::MessageHasTooManyAttachments,
-        "rate_limited" => ErrorKind::RateLimited,
+        "rate_limited" => ErrorKind::RateLimited(None),
+        "ratelimited" => ErrorKind::RateLimited(None),
+        "token_expired" => ErrorKind::TokenExpired,
+        "missing_scope" => ErrorKind::MissingScope(Vec::new(), Vec::new()),
         _ => ErrorKind::Unknown(error_string.clone()),
     };
 
     kind.into()
 }
+
+/// Name of the variant of `kind`, for example, `ChannelNotFound`.
+pub fn error_kind_name(kind: &ErrorKind) -> &'static str {
+    match *kind {
+        ErrorKind::Msg(_) => "Msg",
+        ErrorKind::HttpError(_) => "HttpError",
+        ErrorKind::IoError(_) => "IoError",
+        ErrorKind::JsonParseError(_) => "JsonParseError",
+        ErrorKind::UrlParseError(_) => "UrlParseError",
+        ErrorKind::NoAuthToken => "NoAuthToken",
+        ErrorKind::InvalidAuthToken => "InvalidAuthToken",
+        ErrorKind::InactiveAuthToken => "InactiveAuthToken",
+        ErrorKind::MethodForbiddenForBots => "MethodForbiddenForBots",
+        ErrorKind::MethodForbiddenForRestrictedUser => "MethodForbiddenForRestrictedUser",
+        ErrorKind::MethodForbiddenForSingleChannelGuest => "MethodForbiddenForSingleChannelGuest",
+        ErrorKind::UserNotFound => "UserNotFound",
+        ErrorKind::InvalidChannelId => "InvalidChannelId",
+        ErrorKind::InvalidUserId => "InvalidUserId",
+        ErrorKind::InvalidMethodArg => "InvalidMethodArg",
+        ErrorKind::InvalidArrayArg => "InvalidArrayArg",
+        ErrorKind::InvalidCharset => "InvalidCharset",
+        ErrorKind::InvalidFormData => "InvalidFormData",
+        ErrorKind::InvalidPostType => "InvalidPostType",
+        ErrorKind::MissingPostType => "MissingPostType",
+        ErrorKind::RequestTimeout => "RequestTimeout",
+        ErrorKind::InternalError => "InternalError",
+        ErrorKind::FatalError => "FatalError",
+        ErrorKind::ChannelActionRestricted => "ChannelActionRestricted",
+        ErrorKind::NoChannel => "NoChannel",
+        ErrorKind::ChannelNameTaken => "ChannelNameTaken",
+        ErrorKind::NotInChannel => "NotInChannel",
+        ErrorKind::ChannelIsArchived => "ChannelIsArchived",
+        ErrorKind::CannotArchiveGeneralChannel => "CannotArchiveGeneralChannel",
+        ErrorKind::CannotInviteSelfToChannel => "CannotInviteSelfToChannel",
+        ErrorKind::UserAlreadyMemberOfChannel => "UserAlreadyMemberOfChannel",
+        ErrorKind::CannotInviteUserToChannel => "CannotInviteUserToChannel",
+        ErrorKind::TooManyUsersInvitedAtOnce => "TooManyUsersInvitedAtOnce",
+        ErrorKind::CannotArchiveLastRestrictedActionChannel => "CannotArchiveLastRestrictedActionChannel",
+        ErrorKind::ChannelNotFound => "ChannelNotFound",
+        ErrorKind::CannotLeaveGeneralChannel => "CannotLeaveGeneralChannel",
+        ErrorKind::InvalidTimestamp => "InvalidTimestamp",
+        ErrorKind::ChannelPurposeOrTopicTooLong => "ChannelPurposeOrTopicTooLong",
+        ErrorKind::ChannelNotArchived => "ChannelNotArchived",
+        ErrorKind::MessageNotFound => "MessageNotFound",
+        ErrorKind::UserCannotDeleteMessage => "UserCannotDeleteMessage",
+        ErrorKind::ComplianceExportsPreventDeletion => "ComplianceExportsPreventDeletion",
+        ErrorKind::NoMessageContent => "NoMessageContent",
+        ErrorKind::MessageTooLong => "MessageTooLong",
+        ErrorKind::MessageHasNoText => "MessageHasNoText",
+        ErrorKind::MessageHasTooManyAttachments => "MessageHasTooManyAttachments",
+        ErrorKind::RateLimited(_) => "RateLimited",
+        ErrorKind::MissingScope(..) => "MissingScope",
+        ErrorKind::TokenExpired => "TokenExpired",
+        ErrorKind::TokenRefreshFailed(_) => "TokenRefreshFailed",
+        ErrorKind::CassetteError(_) => "CassetteError",
+        ErrorKind::Unknown(_) => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper;
+    use serde_json::{self, Value};
+    use std::io;
+    use url;
+
+    #[test]
+    fn name_error_kinds() {
+        assert_eq!(error_kind_name(&ErrorKind::ChannelNotFound), "ChannelNotFound");
+
+        let io_error = || io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        let json_error = serde_json::from_str::<Value>("{").unwrap_err();
+        let kinds = vec![(ErrorKind::Msg("oops".to_string()), "Msg"),
+                         (ErrorKind::HttpError(hyper::Error::Io(io_error())), "HttpError"),
+                         (ErrorKind::IoError(io_error()), "IoError"),
+                         (ErrorKind::JsonParseError(json_error), "JsonParseError"),
+                         (ErrorKind::UrlParseError(url::ParseError::EmptyHost), "UrlParseError"),
+                         (ErrorKind::RateLimited(None), "RateLimited"),
+                         (ErrorKind::MissingScope(vec!["chat:write".to_string()], Vec::new()), "MissingScope"),
+                         (ErrorKind::TokenRefreshFailed("invalid_refresh_token".to_string()), "TokenRefreshFailed"),
+                         (ErrorKind::CassetteError("no more requests".to_string()), "CassetteError"),
+                         (ErrorKind::Unknown("oops".to_string()), "Unknown")];
+        for (kind, name) in kinds {
+            assert_eq!(error_kind_name(&kind), name);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use hyper::method::Method;
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use base_types::{ChannelId, Timestamp, UserId};
+use chat;
+use errors::*;
+use retry;
+use serde_types::*;
+
+/// Describes a Slack Web API method: its name, the arguments
+/// it's called with, the response it returns and how it's
+/// called over HTTP.
+///
+/// `Slack::call` can call any type that implements this trait,
+/// so API methods this crate doesn't wrap can be added without
+/// modifying it. Implementations are usually unit structs:
+///
+/// ```ignore
+/// struct RemindersList;
+///
+/// impl SlackMethod for RemindersList {
+///     type Args = ();
+///     type Response = RemindersListResponse;
+///
+///     fn name() -> &'static str {
+///         "reminders.list"
+///     }
+///
+///     fn arguments(_: &()) -> Result<Vec<(String, String)>> {
+///         Ok(Vec::new())
+///     }
+/// }
+///
+/// let reminders = try!(slack.call::<RemindersList>(&()));
+/// ```
+pub trait SlackMethod {
+    /// Arguments with which the method is called.
+    type Args: ?Sized;
+    /// Response returned by the method.
+    type Response: Deserialize + Serialize + ApiResponse;
+
+    /// Name of the method, for example, `chat.postMessage`.
+    fn name() -> &'static str;
+
+    /// HTTP method with which the method is called. Arguments are sent
+    /// in the query string of `GET` requests and in the body otherwise.
+    /// The default is `POST`.
+    fn http_method() -> Method {
+        Method::Post
+    }
+
+    /// OAuth scopes that allow the method to be called; the access token
+    /// needs only one of them. The default, an empty slice, means that
+    /// the method's scopes aren't known and so aren't checked before
+    /// it's called.
+    fn accepted_scopes() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// `true` if calling the method more than once has the same effect
+    /// as calling it once, so that it can safely be retried after a
+    /// transient failure. The default judges by the method's name:
+    /// methods such as `*.info`, `*.list` and `*.history` are idempotent.
+    fn idempotent() -> bool {
+        retry::is_idempotent(Self::name())
+    }
+
+    /// Validate `args` and encode them as name-value pairs.
+    fn arguments(args: &Self::Args) -> Result<Vec<(String, String)>>;
+
+    /// Encode `args` as a JSON object, if the method accepts a JSON body
+    /// that can't be derived from its name-value pairs (for example, because
+    /// an argument is itself structured). The default is `None`.
+    fn json_body(_args: &Self::Args) -> Option<Result<String>> {
+        None
+    }
+}
+
+/// Fields common to the responses of all Slack API methods.
+pub trait ApiResponse {
+    /// `true` if the call succeeded.
+    fn ok(&self) -> bool;
+    /// Error string (for example, `channel_not_found`) if the call failed.
+    fn error(&self) -> Option<&str>;
+    /// Warning string (for example, `missing_charset`), if any.
+    fn warning(&self) -> Option<&str>;
+
+    /// Scopes needed by the method, if the error is `missing_scope`. This is
+    /// the response's `needed` field: scopes separated by commas, any one of
+    /// which would have allowed the call (for example, `chat:write,chat:write:bot`).
+    fn needed_scopes(&self) -> Option<&str> {
+        None
+    }
+
+    /// Scopes granted to the token, if the error is `missing_scope`. This
+    /// is the response's `provided` field: scopes separated by commas.
+    fn provided_scopes(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl ApiResponse for serde_json::Value {
+    fn ok(&self) -> bool {
+        self.find("ok").and_then(|ok| ok.as_bool()).unwrap_or(false)
+    }
+
+    fn error(&self) -> Option<&str> {
+        self.find("error").and_then(|e| e.as_str())
+    }
+
+    fn warning(&self) -> Option<&str> {
+        self.find("warning").and_then(|w| w.as_str())
+    }
+
+    fn needed_scopes(&self) -> Option<&str> {
+        self.find("needed").and_then(|n| n.as_str())
+    }
+
+    fn provided_scopes(&self) -> Option<&str> {
+        self.find("provided").and_then(|p| p.as_str())
+    }
+}
+
+macro_rules! api_response {
+    ($($t:ty),*) => {
+        $(impl ApiResponse for $t {
+            fn ok(&self) -> bool {
+                self.ok
+            }
+
+            fn error(&self) -> Option<&str> {
+                self.error.as_ref().map(|e| e.as_str())
+            }
+
+            fn warning(&self) -> Option<&str> {
+                self.warning.as_ref().map(|w| w.as_str())
+            }
+
+            fn needed_scopes(&self) -> Option<&str> {
+                self.needed.as_ref().map(|n| n.as_str())
+            }
+
+            fn provided_scopes(&self) -> Option<&str> {
+                self.provided.as_ref().map(|p| p.as_str())
+            }
+        })*
+    };
+}
+
+api_response!(ApiTestResponse,
+              ChannelsArchiveResponse,
+              ChannelsCreateResponse,
+              ChannelsInfoResponse,
+              ChannelsInviteResponse,
+              ChannelsJoinResponse,
+              ChannelsLeaveResponse,
+              ChannelsListResponse,
+              ChannelsMarkResponse,
+              ChannelsRenameResponse,
+              ChannelsSetPurposeResponse,
+              ChannelsSetTopicResponse,
+              ChannelsUnarchiveResponse,
+              ChatDeleteResponse,
+              ChatMeMessageResponse,
+              ChatPostMessageResponse,
+              ConversationsHistoryResponse,
+              OAuthV2AccessResponse);
+
+// Accepted scopes include both granular and legacy ("classic") scopes.
+// The legacy `bot` scope allows bot tokens to call any method.
+static CHANNELS_READ: &'static [&'static str] = &["channels:read", "bot"];
+static CHANNELS_WRITE: &'static [&'static str] = &["channels:write", "bot"];
+static CHANNELS_JOIN: &'static [&'static str] = &["channels:join", "channels:write", "bot"];
+static CHANNELS_MANAGE: &'static [&'static str] = &["channels:manage", "channels:write", "bot"];
+static CHAT_WRITE: &'static [&'static str] = &["chat:write", "chat:write:bot", "chat:write:user", "bot"];
+static CONVERSATIONS_HISTORY: &'static [&'static str] = &["channels:history", "groups:history", "im:history",
+                                                          "mpim:history", "bot"];
+
+macro_rules! slack_method {
+    ($(#[$attr:meta])* $method:ident, $name:expr, $args:ty, $response:ty, $scopes:expr, |$a:ident| $arguments:expr) => {
+        $(#[$attr])*
+        pub struct $method;
+
+        impl SlackMethod for $method {
+            type Args = $args;
+            type Response = $response;
+
+            fn name() -> &'static str {
+                $name
+            }
+
+            fn accepted_scopes() -> &'static [&'static str] {
+                $scopes
+            }
+
+            fn arguments($a: &$args) -> Result<Vec<(String, String)>> {
+                $arguments
+            }
+        }
+    };
+}
+
+//
+// api
+//
+
+slack_method!(/// The `api.test` method.
+              ApiTest,
+              "api.test",
+              HashMap<String, String>,
+              ApiTestResponse,
+              &[],
+              |args| Ok(args.iter().map(|(k, v)| (k.clone(), v.clone())).collect()));
+
+//
+// channels
+//
+
+slack_method!(/// The `channels.archive` method.
+              ChannelsArchive,
+              "channels.archive",
+              ChannelId,
+              ChannelsArchiveResponse,
+              CHANNELS_MANAGE,
+              |channel| channel_arguments(channel, &[]));
+
+slack_method!(/// The `channels.create` method.
+              ChannelsCreate,
+              "channels.create",
+              str,
+              ChannelsCreateResponse,
+              CHANNELS_MANAGE,
+              |name| Ok(vec![pair("name", name)]));
+
+slack_method!(/// The `channels.info` method.
+              ChannelsInfo,
+              "channels.info",
+              ChannelId,
+              ChannelsInfoResponse,
+              CHANNELS_READ,
+              |channel| channel_arguments(channel, &[]));
+
+slack_method!(/// The `channels.invite` method.
+              ChannelsInvite,
+              "channels.invite",
+              (ChannelId, UserId),
+              ChannelsInviteResponse,
+              CHANNELS_MANAGE,
+              |args| channel_arguments(&args.0, &[("user", args.1.as_str())]));
+
+slack_method!(/// The `channels.join` method.
+              ChannelsJoin,
+              "channels.join",
+              str,
+              ChannelsJoinResponse,
+              CHANNELS_JOIN,
+              |name| Ok(vec![pair("name", name)]));
+
+slack_method!(/// The `channels.leave` method.
+              ChannelsLeave,
+              "channels.leave",
+              ChannelId,
+              ChannelsLeaveResponse,
+              CHANNELS_MANAGE,
+              |channel| channel_arguments(channel, &[]));
+
+slack_method!(/// The `channels.list` method.
+              ChannelsList,
+              "channels.list",
+              bool,
+              ChannelsListResponse,
+              CHANNELS_READ,
+              |exclude_archived| Ok(vec![pair("exclude_archived", &exclude_archived.to_string())]));
+
+slack_method!(/// The `channels.mark` method.
+              ChannelsMark,
+              "channels.mark",
+              (ChannelId, Timestamp),
+              ChannelsMarkResponse,
+              CHANNELS_WRITE,
+              |args| channel_arguments(&args.0, &[("ts", args.1.as_str())]));
+
+slack_method!(/// The `channels.rename` method.
+              ChannelsRename,
+              "channels.rename",
+              (ChannelId, String),
+              ChannelsRenameResponse,
+              CHANNELS_MANAGE,
+              |args| channel_arguments(&args.0, &[("name", &args.1)]));
+
+slack_method!(/// The `channels.setPurpose` method.
+              ChannelsSetPurpose,
+              "channels.setPurpose",
+              (ChannelId, String),
+              ChannelsSetPurposeResponse,
+              CHANNELS_MANAGE,
+              |args| channel_arguments(&args.0, &[("purpose", &args.1)]));
+
+slack_method!(/// The `channels.setTopic` method.
+              ChannelsSetTopic,
+              "channels.setTopic",
+              (ChannelId, String),
+              ChannelsSetTopicResponse,
+              CHANNELS_MANAGE,
+              |args| channel_arguments(&args.0, &[("topic", &args.1)]));
+
+slack_method!(/// The `channels.unarchive` method.
+              ChannelsUnarchive,
+              "channels.unarchive",
+              ChannelId,
+              ChannelsUnarchiveResponse,
+              CHANNELS_MANAGE,
+              |channel| channel_arguments(channel, &[]));
+
+//
+// chat
+//
+
+slack_method!(/// The `chat.delete` method.
+              ChatDelete,
+              "chat.delete",
+              (ChannelId, Timestamp, Option<bool>),
+              ChatDeleteResponse,
+              CHAT_WRITE,
+              |args| {
+                  let mut arguments = try!(channel_arguments(&args.0, &[("ts", args.1.as_str())]));
+                  args.2.map(|as_user| arguments.push(pair("as_user", &as_user.to_string())));
+                  Ok(arguments)
+              });
+
+slack_method!(/// The `chat.meMessage` method.
+              ChatMeMessage,
+              "chat.meMessage",
+              (ChannelId, String),
+              ChatMeMessageResponse,
+              CHAT_WRITE,
+              |args| channel_arguments(&args.0, &[("text", &args.1)]));
+
+/// The `chat.postMessage` method.
+pub struct ChatPostMessage;
+
+impl SlackMethod for ChatPostMessage {
+    type Args = (ChannelId, Message);
+    type Response = ChatPostMessageResponse;
+
+    fn name() -> &'static str {
+        "chat.postMessage"
+    }
+
+    fn accepted_scopes() -> &'static [&'static str] {
+        CHAT_WRITE
+    }
+
+    fn arguments(args: &(ChannelId, Message)) -> Result<Vec<(String, String)>> {
+        let mut arguments = try!(channel_arguments(&args.0, &[]));
+        let message_arguments = try!(chat::encode_message(&args.1));
+        arguments.extend(message_arguments.into_iter().map(|(name, value)| (name.to_string(), value)));
+        Ok(arguments)
+    }
+
+    // attachments are sent as a JSON array rather than a string
+    fn json_body(args: &(ChannelId, Message)) -> Option<Result<String>> {
+        Some(chat::encode_message_json(args.0.as_str(), &args.1))
+    }
+}
+
+//
+// conversations
+//
+
+slack_method!(/// The `conversations.history` method, called with the
+              /// channel and the maximum number of messages to return.
+              /// Message metadata is included.
+              ConversationsHistory,
+              "conversations.history",
+              (ChannelId, u32),
+              ConversationsHistoryResponse,
+              CONVERSATIONS_HISTORY,
+              |args| {
+                  channel_arguments(&args.0,
+                                    &[("limit", &args.1.to_string()), ("include_all_metadata", "true")])
+              });
+
+fn pair(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+// Arguments for a method that targets `channel`.
+fn channel_arguments(channel: &ChannelId, arguments: &[(&str, &str)]) -> Result<Vec<(String, String)>> {
+    let mut encoded = vec![pair("channel", channel.as_str())];
+    encoded.extend(arguments.iter().map(|&(name, value)| pair(name, value)));
+    Ok(encoded)
+}
@@ -0,0 +1,209 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::status::StatusCode;
+
+// Upper bounds of the latency histogram buckets, in milliseconds.
+static LATENCY_BUCKET_MILLIS: &'static [u64] = &[10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Outcome of a single API call made by `Slack`.
+#[derive(Clone, Debug)]
+pub struct CallMetrics<'a> {
+    /// API method called, for example, `chat.postMessage`.
+    pub method: &'a str,
+    /// Time taken by the call, including any retries.
+    pub duration: Duration,
+    /// HTTP status of the last response, or `None` if
+    /// no response was received.
+    pub status: Option<StatusCode>,
+    /// Name of the `ErrorKind` variant with which the call failed
+    /// (for example, `ChannelNotFound`), or `None` if it succeeded.
+    pub error: Option<String>,
+    /// Number of times the call was retried, because it was
+    /// rate-limited, failed transiently or its token expired.
+    pub retries: u32,
+}
+
+/// Receives the outcome of every API call made by `Slack`,
+/// for example, to export them to a metrics system.
+///
+/// Observers are called on the thread that made the call,
+/// so they should return quickly.
+pub trait MetricsObserver: Send + Sync {
+    /// Record the outcome of an API call.
+    fn observe(&self, call: &CallMetrics);
+}
+
+impl<T> MetricsObserver for Arc<T> where T: MetricsObserver + ?Sized {
+    fn observe(&self, call: &CallMetrics) {
+        (**self).observe(call)
+    }
+}
+
+/// `MetricsObserver` that aggregates API call outcomes in memory,
+/// into counters and latency histograms for each method.
+///
+/// Share it between `Slack` instances with an `Arc`, and
+/// periodically export a `snapshot` to a metrics system.
+#[derive(Default)]
+pub struct InMemoryMetrics {
+    methods: Mutex<HashMap<String, MethodMetrics>>,
+}
+
+/// Aggregated outcomes of the calls to a single API method.
+#[derive(Clone, Debug, Default)]
+pub struct MethodMetrics {
+    /// Number of calls made.
+    pub calls: u64,
+    /// Number of failed calls, by `ErrorKind` variant name.
+    pub errors: HashMap<String, u64>,
+    /// Number of calls, by HTTP status of their last response.
+    pub statuses: HashMap<u16, u64>,
+    /// Total number of retries.
+    pub retries: u64,
+    /// Latency of the calls, including retries.
+    pub latency: Histogram,
+}
+
+/// Histogram of durations, with fixed buckets from 10ms to 10s.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: Duration,
+}
+
+impl InMemoryMetrics {
+    /// Create an aggregator with no recorded calls.
+    pub fn new() -> InMemoryMetrics {
+        InMemoryMetrics::default()
+    }
+
+    /// Aggregated outcomes of the calls to `method`, if any were made.
+    pub fn method(&self, method: &str) -> Option<MethodMetrics> {
+        self.methods.lock().unwrap().get(method).cloned()
+    }
+
+    /// Aggregated outcomes of the calls to every method, keyed by method.
+    pub fn snapshot(&self) -> HashMap<String, MethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+
+    /// Forget every recorded call.
+    pub fn reset(&self) {
+        self.methods.lock().unwrap().clear();
+    }
+}
+
+impl MetricsObserver for InMemoryMetrics {
+    fn observe(&self, call: &CallMetrics) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry(call.method.to_string()).or_insert_with(MethodMetrics::default);
+        metrics.calls += 1;
+        if let Some(ref error) = call.error {
+            *metrics.errors.entry(error.clone()).or_insert(0) += 1;
+        }
+        if let Some(status) = call.status {
+            *metrics.statuses.entry(status.to_u16()).or_insert(0) += 1;
+        }
+        metrics.retries += call.retries as u64;
+        metrics.latency.record(call.duration);
+    }
+}
+
+impl Histogram {
+    /// Count of durations in each bucket, paired with the bucket's
+    /// (inclusive) upper bound. The last bucket has no upper bound.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        let bounds = LATENCY_BUCKET_MILLIS.iter().map(|&millis| Some(Duration::from_millis(millis)));
+        bounds.chain(Some(None)).zip(self.counts.iter().cloned()).collect()
+    }
+
+    /// Number of durations recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of the durations recorded.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    fn record(&mut self, duration: Duration) {
+        let bucket = LATENCY_BUCKET_MILLIS.iter()
+            .position(|&millis| duration <= Duration::from_millis(millis))
+            .unwrap_or(LATENCY_BUCKET_MILLIS.len());
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += duration;
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram {
+            counts: vec![0; LATENCY_BUCKET_MILLIS.len() + 1],
+            count: 0,
+            sum: Duration::from_millis(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use hyper::status::StatusCode;
+
+    fn call(duration_millis: u64, status: Option<StatusCode>, error: Option<&str>, retries: u32) -> CallMetrics<'static> {
+        CallMetrics {
+            method: "channels.info",
+            duration: Duration::from_millis(duration_millis),
+            status: status,
+            error: error.map(|e| e.to_string()),
+            retries: retries,
+        }
+    }
+
+    #[test]
+    fn aggregate_calls_by_method() {
+        let metrics = InMemoryMetrics::new();
+        metrics.observe(&call(5, Some(StatusCode::Ok), None, 0));
+        metrics.observe(&call(30, Some(StatusCode::Ok), Some("ChannelNotFound"), 0));
+        metrics.observe(&call(20000, None, Some("IoError"), 2));
+
+        let info = metrics.method("channels.info").unwrap();
+        assert_eq!(info.calls, 3);
+        assert_eq!(info.retries, 2);
+        assert_eq!(info.statuses.get(&200), Some(&2));
+        assert_eq!(info.errors.get("ChannelNotFound"), Some(&1));
+        assert_eq!(info.errors.get("IoError"), Some(&1));
+
+        let buckets = info.latency.buckets();
+        assert_eq!(buckets[0], (Some(Duration::from_millis(10)), 1));
+        assert_eq!(buckets[2], (Some(Duration::from_millis(50)), 1));
+        assert_eq!(buckets[buckets.len() - 1], (None, 1));
+        assert_eq!(info.latency.count(), 3);
+        assert_eq!(info.latency.sum(), Duration::from_millis(20035));
+
+        assert!(metrics.method("channels.list").is_none());
+        metrics.reset();
+        assert!(metrics.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,427 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use hyper::Url;
+use serde_json;
+
+use auth::{AccessToken, TokenProvider};
+use errors::*;
+use serde_types::OAuthV2AccessResponse;
+use transport::{HttpConfig, HyperTransport, RequestEncoding, Transport};
+
+static OAUTH_V2_ACCESS_URL: &'static str = "https://slack.com/api/oauth.v2.access";
+const DEFAULT_REFRESH_MARGIN_SECS: u64 = 5 * 60;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Access token and the refresh token with which it can be replaced
+/// once it expires. See [Token rotation](https://api.slack.com/authentication/rotation "Token rotation")
+#[derive(Clone, Debug)]
+pub struct TokenPair {
+    /// Token with which API calls are made.
+    pub access_token: AccessToken,
+    /// Token with which a new `TokenPair` is obtained.
+    pub refresh_token: AccessToken,
+    /// Time at which `access_token` expires, or `None` if it doesn't.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// `TokenProvider` for rotating tokens, which expire after a
+/// few hours and must be refreshed via `oauth.v2.access`.
+///
+/// The token is refreshed shortly before it expires (and whenever
+/// Slack reports that it has expired). Each new `TokenPair` is passed
+/// to a callback so that it can be persisted; the refresh token it
+/// replaces can't be used again.
+///
+/// Only one thread refreshes the token at a time. Until the token
+/// expires, other threads keep using it meanwhile, and it's still
+/// used if refreshing it fails.
+pub struct RefreshingTokenProvider {
+    client_id: String,
+    client_secret: AccessToken,
+    url: String,
+    refresh_margin: Duration,
+    transport: Box<Transport>,
+    on_refresh: Box<Fn(&TokenPair) + Send + Sync>,
+    state: Mutex<TokenState>,
+    refreshed: Condvar,
+}
+
+struct TokenState {
+    tokens: TokenPair,
+    // `true` while a thread is refreshing `tokens`
+    refreshing: bool,
+}
+
+impl TokenState {
+    fn expires_within(&self, margin: Duration) -> bool {
+        self.tokens.expires_at.map_or(false, |expires_at| SystemTime::now() + margin >= expires_at)
+    }
+}
+
+impl RefreshingTokenProvider {
+    /// Create a provider that refreshes `tokens` with the credentials
+    /// of the app with id `client_id`, and passes each new token pair
+    /// to `on_refresh`. If the access token in `tokens` isn't known,
+    /// set `expires_at` to `Some(UNIX_EPOCH)` to refresh it before first use.
+    pub fn new<F>(client_id: &str, client_secret: &str, tokens: TokenPair, on_refresh: F) -> RefreshingTokenProvider
+        where F: Fn(&TokenPair) + Send + Sync + 'static
+    {
+        RefreshingTokenProvider {
+            client_id: client_id.to_string(),
+            client_secret: AccessToken::new(client_secret),
+            url: OAUTH_V2_ACCESS_URL.to_string(),
+            refresh_margin: Duration::from_secs(DEFAULT_REFRESH_MARGIN_SECS),
+            transport: Box::new(HyperTransport::with_config(&HttpConfig {
+                read_timeout: Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS)),
+                write_timeout: Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS)),
+                ..HttpConfig::default()
+            })),
+            on_refresh: Box::new(on_refresh),
+            state: Mutex::new(TokenState {
+                tokens: tokens,
+                refreshing: false,
+            }),
+            refreshed: Condvar::new(),
+        }
+    }
+
+    /// Refresh tokens by calling `oauth.v2.access` via `transport`. The default
+    /// is a `HyperTransport` with 30 second read and write timeouts; threads
+    /// that need a new access token wait for the refresh, so the transport
+    /// shouldn't wait indefinitely.
+    pub fn transport<T>(mut self, transport: T) -> RefreshingTokenProvider where T: Transport + 'static {
+        self.transport = Box::new(transport);
+        self
+    }
+
+    /// Refresh tokens by calling `oauth.v2.access` at the Web API at
+    /// `base_url` instead of `https://slack.com/api`.
+    pub fn base_url(mut self, base_url: &str) -> RefreshingTokenProvider {
+        self.url = format!("{}/oauth.v2.access", base_url.trim_right_matches('/'));
+        self
+    }
+
+    /// Refresh the access token once it's due to expire within
+    /// `refresh_margin`. The default is five minutes.
+    pub fn refresh_margin(mut self, refresh_margin: Duration) -> RefreshingTokenProvider {
+        self.refresh_margin = refresh_margin;
+        self
+    }
+
+    /// Current token pair.
+    pub fn tokens(&self) -> TokenPair {
+        self.state.lock().unwrap().tokens.clone()
+    }
+
+    // Refresh the tokens in `state`, releasing the lock while waiting for
+    // Slack so that other threads can keep using the current access token.
+    // The new tokens are passed to `on_refresh` without holding the lock,
+    // but before another refresh can start, so they're persisted in order.
+    fn refresh_tokens<'a>(&'a self, mut state: MutexGuard<'a, TokenState>) -> (MutexGuard<'a, TokenState>, Result<()>) {
+        state.refreshing = true;
+        let tokens = state.tokens.clone();
+        drop(state);
+
+        let refreshing = Refreshing { provider: self };
+        let result = self.request_tokens(&tokens).map(|tokens| {
+            self.state.lock().unwrap().tokens = tokens.clone();
+            self.refreshed.notify_all();
+            (self.on_refresh)(&tokens);
+        });
+        drop(refreshing);
+        (self.state.lock().unwrap(), result)
+    }
+
+    // Exchange the refresh token in `tokens` for a new token pair.
+    fn request_tokens(&self, tokens: &TokenPair) -> Result<TokenPair> {
+        let url = try!(Url::parse(&self.url));
+        let arguments = [("grant_type", "refresh_token"),
+                         ("refresh_token", tokens.refresh_token.secret()),
+                         ("client_id", &self.client_id),
+                         ("client_secret", self.client_secret.secret())];
+        let request = try!(RequestEncoding::Form.encode(url, &arguments));
+
+        debug!("refreshing access token {}", tokens.access_token);
+        let mut response = try!(self.transport.send(&request));
+        let mut body = String::new();
+        try!(response.body.read_to_string(&mut body));
+        let deserialized: OAuthV2AccessResponse = try!(serde_json::from_str(&body));
+
+        match (deserialized.ok, deserialized.access_token, deserialized.refresh_token) {
+            (true, Some(access_token), Some(refresh_token)) => {
+                Ok(TokenPair {
+                    access_token: AccessToken::from(access_token),
+                    refresh_token: AccessToken::from(refresh_token),
+                    // tokens without an expiry don't expire
+                    expires_at: deserialized.expires_in.map(|secs| SystemTime::now() + Duration::from_secs(secs)),
+                })
+            }
+            (true, _, _) => Err(ErrorKind::TokenRefreshFailed("no token in response".to_string()).into()),
+            (false, _, _) => {
+                let error = deserialized.error.unwrap_or_else(|| "unknown_error".to_string());
+                Err(ErrorKind::TokenRefreshFailed(error).into())
+            }
+        }
+    }
+}
+
+// Marks the end of a refresh when dropped, so that threads waiting for
+// it are woken even if requesting the tokens or `on_refresh` panics.
+struct Refreshing<'a> {
+    provider: &'a RefreshingTokenProvider,
+}
+
+impl<'a> Drop for Refreshing<'a> {
+    fn drop(&mut self) {
+        let mut state = self.provider.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.refreshing = false;
+        self.provider.refreshed.notify_all();
+    }
+}
+
+impl TokenProvider for RefreshingTokenProvider {
+    fn access_token(&self) -> Result<AccessToken> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if !state.expires_within(self.refresh_margin) {
+                return Ok(state.tokens.access_token.clone());
+            }
+            // another thread is refreshing the token; wait for it only if the token has expired
+            if state.refreshing {
+                if !state.expires_within(Duration::from_secs(0)) {
+                    return Ok(state.tokens.access_token.clone());
+                }
+                state = self.refreshed.wait(state).unwrap();
+                continue;
+            }
+
+            let (refreshed, result) = self.refresh_tokens(state);
+            state = refreshed;
+            match result {
+                Ok(()) => return Ok(state.tokens.access_token.clone()),
+                Err(ref e) if !state.expires_within(Duration::from_secs(0)) => {
+                    warn!("failed to refresh access token {}: {}; using it until it expires",
+                          state.tokens.access_token,
+                          e);
+                    return Ok(state.tokens.access_token.clone());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn refresh(&self, expired: &AccessToken) -> Result<AccessToken> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            // another thread may have refreshed the token already
+            if state.tokens.access_token != *expired {
+                return Ok(state.tokens.access_token.clone());
+            }
+            if state.refreshing {
+                state = self.refreshed.wait(state).unwrap();
+                continue;
+            }
+
+            let (refreshed, result) = self.refresh_tokens(state);
+            try!(result);
+            return Ok(refreshed.tokens.access_token.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use hyper::header::Headers;
+    use hyper::status::StatusCode;
+    use url::form_urlencoded;
+
+    use auth::{AccessToken, TokenProvider};
+    use errors::ErrorKind;
+    use transport::{HttpRequest, HttpResponse, Transport};
+
+    // Transport that hands out numbered token pairs and
+    // records the refresh token used to request each.
+    struct RefreshTransport {
+        refresh_tokens: Mutex<Vec<String>>,
+        // token pairs expire after this many seconds, if any
+        expires_in: Option<u64>,
+        // fail every refresh with this error, if any
+        error: Option<&'static str>,
+        // panic instead of responding
+        panic: bool,
+        delay: Duration,
+    }
+
+    impl RefreshTransport {
+        fn new() -> RefreshTransport {
+            RefreshTransport {
+                refresh_tokens: Mutex::new(Vec::new()),
+                expires_in: Some(43200),
+                error: None,
+                panic: false,
+                delay: Duration::from_secs(0),
+            }
+        }
+    }
+
+    impl Transport for RefreshTransport {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+            let arguments: Vec<(String, String)> =
+                form_urlencoded::parse(request.body.as_ref().unwrap()).into_owned().collect();
+            assert!(arguments.contains(&("grant_type".to_string(), "refresh_token".to_string())));
+            let refresh_token = arguments.iter().find(|&&(ref n, _)| n == "refresh_token").unwrap().1.clone();
+            thread::sleep(self.delay);
+            if self.panic {
+                panic!("connection lost");
+            }
+
+            let mut refresh_tokens = self.refresh_tokens.lock().unwrap();
+            refresh_tokens.push(refresh_token);
+            let body = match (self.error, self.expires_in) {
+                (Some(error), _) => format!(r#"{{"ok": false, "error": "{}"}}"#, error),
+                (None, Some(expires_in)) => {
+                    format!(r#"{{"ok": true, "access_token": "xoxe.xoxb-{0}", "refresh_token": "xoxe-{0}",
+                                 "expires_in": {1}}}"#,
+                            refresh_tokens.len(),
+                            expires_in)
+                }
+                (None, None) => {
+                    format!(r#"{{"ok": true, "access_token": "xoxe.xoxb-{0}", "refresh_token": "xoxe-{0}"}}"#,
+                            refresh_tokens.len())
+                }
+            };
+            Ok(HttpResponse {
+                status: StatusCode::Ok,
+                headers: Headers::new(),
+                body: Box::new(Cursor::new(body.into_bytes())),
+            })
+        }
+    }
+
+    fn provider(expires_at: SystemTime,
+                transport: RefreshTransport)
+                -> (RefreshingTokenProvider, Arc<RefreshTransport>, Arc<Mutex<Vec<TokenPair>>>) {
+        let transport = Arc::new(transport);
+        let persisted = Arc::new(Mutex::new(Vec::new()));
+        let tokens = TokenPair {
+            access_token: AccessToken::new("xoxe.xoxb-0"),
+            refresh_token: AccessToken::new("xoxe-0"),
+            expires_at: Some(expires_at),
+        };
+        let on_refresh = {
+            let persisted = persisted.clone();
+            move |tokens: &TokenPair| persisted.lock().unwrap().push(tokens.clone())
+        };
+        let provider = RefreshingTokenProvider::new("client", "secret", tokens, on_refresh).transport(transport.clone());
+        (provider, transport, persisted)
+    }
+
+    #[test]
+    fn refresh_ahead_of_expiry() {
+        let (provider, transport, persisted) = provider(SystemTime::now() + Duration::from_secs(60),
+                                                        RefreshTransport::new());
+
+        assert_eq!(provider.access_token().unwrap(), AccessToken::new("xoxe.xoxb-1"));
+        assert_eq!(provider.access_token().unwrap(), AccessToken::new("xoxe.xoxb-1"));
+        assert_eq!(*transport.refresh_tokens.lock().unwrap(), vec!["xoxe-0"]);
+
+        let persisted = persisted.lock().unwrap();
+        assert_eq!(persisted.len(), 1);
+        assert_eq!(persisted[0].refresh_token, AccessToken::new("xoxe-1"));
+        assert!(persisted[0].expires_at.unwrap() > SystemTime::now() + Duration::from_secs(43000));
+    }
+
+    #[test]
+    fn refresh_expired_token_once() {
+        let (provider, transport, _) = provider(UNIX_EPOCH, RefreshTransport::new());
+        let expired = provider.access_token().unwrap();
+
+        let refreshed = provider.refresh(&expired).unwrap();
+        assert_eq!(refreshed, AccessToken::new("xoxe.xoxb-2"));
+        // a stale token doesn't cause another refresh
+        assert_eq!(provider.refresh(&expired).unwrap(), refreshed);
+        assert_eq!(*transport.refresh_tokens.lock().unwrap(), vec!["xoxe-0", "xoxe-1"]);
+    }
+
+    #[test]
+    fn use_valid_token_if_refresh_fails() {
+        let failing = RefreshTransport { error: Some("internal_error"), ..RefreshTransport::new() };
+        let (valid, _, persisted) = provider(SystemTime::now() + Duration::from_secs(60), failing);
+        assert_eq!(valid.access_token().unwrap(), AccessToken::new("xoxe.xoxb-0"));
+        assert!(persisted.lock().unwrap().is_empty());
+
+        let failing = RefreshTransport { error: Some("internal_error"), ..RefreshTransport::new() };
+        let (expired, _, _) = provider(UNIX_EPOCH, failing);
+        match *expired.access_token().unwrap_err().kind() {
+            ErrorKind::TokenRefreshFailed(ref error) => assert_eq!(error, "internal_error"),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn keep_tokens_without_expiry() {
+        let no_expiry = RefreshTransport { expires_in: None, ..RefreshTransport::new() };
+        let (provider, transport, _) = provider(UNIX_EPOCH, no_expiry);
+
+        assert_eq!(provider.access_token().unwrap(), AccessToken::new("xoxe.xoxb-1"));
+        assert_eq!(provider.access_token().unwrap(), AccessToken::new("xoxe.xoxb-1"));
+        assert_eq!(provider.tokens().expires_at, None);
+        assert_eq!(transport.refresh_tokens.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn finish_refresh_if_it_panics() {
+        let panicking = RefreshTransport { panic: true, ..RefreshTransport::new() };
+        let (provider, _, _) = provider(UNIX_EPOCH, panicking);
+        let provider = Arc::new(provider);
+
+        let refreshing = provider.clone();
+        assert!(thread::spawn(move || refreshing.access_token()).join().is_err());
+        assert!(!provider.state.lock().unwrap().refreshing);
+    }
+
+    #[test]
+    fn refresh_once_for_concurrent_callers() {
+        let slow = RefreshTransport { delay: Duration::from_millis(50), ..RefreshTransport::new() };
+        let (provider, transport, _) = provider(UNIX_EPOCH, slow);
+        let provider = Arc::new(provider);
+        let barrier = Arc::new(Barrier::new(4));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let provider = provider.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    provider.access_token().unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), AccessToken::new("xoxe.xoxb-1"));
+        }
+        assert_eq!(transport.refresh_tokens.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often buckets that are full again are dropped, so that
+// per-channel buckets don't accumulate in long-running clients.
+const EVICTION_INTERVAL_SECS: u64 = 60;
+
+/// Rate-limit tier of a Slack API method.
+///
+/// See [Rate Limits](https://api.slack.com/docs/rate-limits "Rate Limits")
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tier {
+    /// 1+ calls per minute.
+    Tier1,
+    /// 20+ calls per minute.
+    Tier2,
+    /// 50+ calls per minute.
+    Tier3,
+    /// 100+ calls per minute.
+    Tier4,
+    /// 1 call per second, per channel. Used by `chat.postMessage`.
+    PostMessage,
+}
+
+impl Tier {
+    /// Tier for `method` as documented by Slack. Methods
+    /// without a documented tier are considered `Tier3`.
+    pub fn for_method(method: &str) -> Tier {
+        match method {
+            "api.test" => Tier::Tier4,
+            "channels.archive" => Tier::Tier2,
+            "channels.create" => Tier::Tier2,
+            "channels.info" => Tier::Tier3,
+            "channels.invite" => Tier::Tier3,
+            "channels.join" => Tier::Tier3,
+            "channels.kick" => Tier::Tier3,
+            "channels.leave" => Tier::Tier3,
+            "channels.list" => Tier::Tier2,
+            "channels.mark" => Tier::Tier3,
+            "channels.rename" => Tier::Tier2,
+            "channels.setPurpose" => Tier::Tier2,
+            "channels.setTopic" => Tier::Tier2,
+            "channels.unarchive" => Tier::Tier2,
+            "chat.delete" => Tier::Tier3,
+            "chat.meMessage" => Tier::Tier3,
+            "chat.postMessage" => Tier::PostMessage,
+            "chat.update" => Tier::Tier3,
+            "reminders.add" => Tier::Tier2,
+            "reminders.complete" => Tier::Tier2,
+            "reminders.delete" => Tier::Tier2,
+            "reminders.info" => Tier::Tier2,
+            "reminders.list" => Tier::Tier2,
+            "search.all" => Tier::Tier2,
+            "search.files" => Tier::Tier2,
+            "search.messages" => Tier::Tier2,
+            "users.deletePhoto" => Tier::Tier2,
+            "users.getPresence" => Tier::Tier3,
+            "users.identity" => Tier::Tier4,
+            "users.info" => Tier::Tier4,
+            "users.list" => Tier::Tier2,
+            "users.setActive" => Tier::Tier2,
+            "users.setPhoto" => Tier::Tier2,
+            "users.setPresence" => Tier::Tier2,
+            "users.profile.get" => Tier::Tier4,
+            "users.profile.set" => Tier::Tier3,
+            _ => Tier::Tier3,
+        }
+    }
+
+    // Number of calls that can be made back-to-back, and
+    // the number of calls allowed per second thereafter.
+    fn bucket_parameters(&self) -> (f64, f64) {
+        match *self {
+            Tier::Tier1 => (1.0, 1.0 / 60.0),
+            Tier::Tier2 => (1.0, 20.0 / 60.0),
+            Tier::Tier3 => (2.0, 50.0 / 60.0),
+            Tier::Tier4 => (5.0, 100.0 / 60.0),
+            Tier::PostMessage => (1.0, 1.0),
+        }
+    }
+}
+
+/// Token bucket for a single API method (or, for
+/// `chat.postMessage`, a single method and channel).
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn new(tier: Tier, now: Instant) -> Bucket {
+        let (capacity, refill_per_sec) = tier.bucket_parameters();
+        Bucket {
+            capacity: capacity,
+            tokens: capacity,
+            refill_per_sec: refill_per_sec,
+            last_refill: now,
+        }
+    }
+
+    // Take a token from the bucket and return how long the caller
+    // must wait before it can be used. The bucket is allowed to go
+    // into deficit so that concurrent callers queue up behind each other.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            let wait_millis = -self.tokens / self.refill_per_sec * 1000.0;
+            Duration::from_millis(wait_millis.round() as u64)
+        }
+    }
+
+    // `true` if the bucket has refilled completely, in which
+    // case it's no different from a newly-created bucket.
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now > self.last_refill {
+            let elapsed = now.duration_since(self.last_refill);
+            let elapsed_secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+            self.tokens = (self.tokens + elapsed_secs * self.refill_per_sec).min(self.capacity);
+            self.last_refill = now;
+        }
+    }
+}
+
+/// Client-side rate limiter that paces API calls
+/// according to each method's rate-limit tier.
+///
+/// A `RateLimiter` is thread-safe, and can be shared (via
+/// `Arc`) by multiple `Slack` instances that use the same
+/// access token so that, together, they stay within budget.
+pub struct RateLimiter {
+    overrides: Mutex<HashMap<String, Tier>>,
+    buckets: Mutex<Buckets>,
+}
+
+// Buckets keyed by method (and channel, for `chat.postMessage`).
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    last_eviction: Instant,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter that uses Slack's documented tiers.
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            overrides: Mutex::new(HashMap::new()),
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_eviction: Instant::now(),
+            }),
+        }
+    }
+
+    /// Pace calls to `method` according to `tier` instead of its documented tier.
+    pub fn set_tier(&self, method: &str, tier: Tier) {
+        self.overrides.lock().unwrap().insert(method.to_string(), tier);
+        // drop the method's buckets so that they're recreated for `tier`
+        let channel_prefix = format!("{}:", method);
+        self.buckets.lock().unwrap().remove_where(|key, _| key == method || key.starts_with(&channel_prefix));
+    }
+
+    /// Tier according to which calls to `method` are paced.
+    pub fn tier(&self, method: &str) -> Tier {
+        self.overrides.lock().unwrap().get(method).cloned().unwrap_or_else(|| Tier::for_method(method))
+    }
+
+    /// Block until a call to `method` can be made. `channel` is
+    /// the channel the call targets, if any; it's only used for
+    /// methods in the `Tier::PostMessage` tier.
+    pub fn acquire(&self, method: &str, channel: Option<&str>) {
+        let wait = self.reserve(method, channel, Instant::now());
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+    }
+
+    fn reserve(&self, method: &str, channel: Option<&str>, now: Instant) -> Duration {
+        let tier = self.tier(method);
+        let key = match (tier, channel) {
+            (Tier::PostMessage, Some(channel)) => format!("{}:{}", method, channel),
+            _ => method.to_string(),
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if now > buckets.last_eviction + Duration::from_secs(EVICTION_INTERVAL_SECS) {
+            buckets.remove_where(|_, bucket| bucket.is_full(now));
+            buckets.last_eviction = now;
+        }
+        buckets.buckets.entry(key).or_insert_with(|| Bucket::new(tier, now)).reserve(now)
+    }
+}
+
+impl Buckets {
+    fn remove_where<F>(&mut self, mut remove: F)
+        where F: FnMut(&str, &mut Bucket) -> bool
+    {
+        let removed: Vec<String> = self.buckets
+            .iter_mut()
+            .filter_map(|(key, bucket)| if remove(key, bucket) { Some(key.clone()) } else { None })
+            .collect();
+        for key in removed {
+            self.buckets.remove(&key);
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn allow_burst_then_pace() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("users.info", None, now), Duration::from_secs(0));
+        for _ in 1..5 {
+            limiter.reserve("users.info", None, now);
+        }
+
+        let wait = limiter.reserve("users.info", None, now);
+        assert!(wait > Duration::from_millis(550) && wait < Duration::from_millis(650));
+    }
+
+    #[test]
+    fn refill_over_time() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("chat.delete", None, now), Duration::from_secs(0));
+        assert_eq!(limiter.reserve("chat.delete", None, now), Duration::from_secs(0));
+        assert_eq!(limiter.reserve("chat.delete", None, now + Duration::from_secs(2)), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn pace_post_message_per_channel() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("chat.postMessage", Some("C1"), now), Duration::from_secs(0));
+        assert_eq!(limiter.reserve("chat.postMessage", Some("C2"), now), Duration::from_secs(0));
+        assert_eq!(limiter.reserve("chat.postMessage", Some("C1"), now), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn pace_tier_overridden_after_calls() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        assert_eq!(limiter.reserve("chat.postMessage", Some("C1"), now), Duration::from_secs(0));
+
+        limiter.set_tier("chat.postMessage", Tier::Tier4);
+        for _ in 0..5 {
+            assert_eq!(limiter.reserve("chat.postMessage", Some("C1"), now), Duration::from_secs(0));
+        }
+    }
+
+    #[test]
+    fn evict_idle_buckets() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for i in 0..100 {
+            limiter.reserve("chat.postMessage", Some(&format!("C{}", i)), now);
+        }
+        limiter.reserve("users.info", None, now + Duration::from_secs(30));
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), 101);
+
+        limiter.reserve("users.info", None, now + Duration::from_secs(61));
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), 1);
+    }
+
+    #[test]
+    fn pace_overridden_tier() {
+        let limiter = RateLimiter::new();
+        limiter.set_tier("users.info", Tier::Tier1);
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("users.info", None, now), Duration::from_secs(0));
+        assert_eq!(limiter.reserve("users.info", None, now), Duration::from_secs(60));
+    }
+}
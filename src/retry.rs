@@ -0,0 +1,268 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::io;
+use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper;
+use hyper::header::Headers;
+
+use errors::*;
+
+static RETRY_AFTER_HEADER: &'static str = "Retry-After";
+const DEFAULT_RETRY_AFTER_SECS: u64 = 1;
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF_MILLIS: u64 = 250;
+const DEFAULT_MAX_BACKOFF_MILLIS: u64 = 8000;
+// backoff stops doubling after this many retries
+const MAX_BACKOFF_DOUBLINGS: u32 = 32;
+
+/// Controls how `Slack` handles API calls rejected
+/// by the server because of rate limits.
+///
+/// By default, `Slack` does not retry rate-limited
+/// calls and instead returns `ErrorKind::RateLimited`.
+///
+/// See [Rate Limits](https://api.slack.com/docs/rate-limits "Rate Limits")
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitPolicy {
+    /// Maximum number of times a single API call is retried.
+    pub max_retries: u32,
+    /// Longest time to wait before retrying an API call. If the
+    /// server asks the client to wait longer, the call fails
+    /// immediately with `ErrorKind::RateLimited`.
+    pub max_retry_after: Duration,
+}
+
+impl RateLimitPolicy {
+    /// Create a policy that retries each rate-limited API call
+    /// at most `max_retries` times, waiting no longer than
+    /// `max_retry_after` before each retry.
+    pub fn new(max_retries: u32, max_retry_after: Duration) -> RateLimitPolicy {
+        RateLimitPolicy {
+            max_retries: max_retries,
+            max_retry_after: max_retry_after,
+        }
+    }
+
+    /// Return how long to wait before retrying a rate-limited call,
+    /// or `None` if the call should not be retried. `retries` is the
+    /// number of times the call has already been retried.
+    pub fn wait_before_retry(&self, retries: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let wait = retry_after.unwrap_or(Duration::from_secs(DEFAULT_RETRY_AFTER_SECS));
+        if retries < self.max_retries && wait <= self.max_retry_after {
+            Some(wait)
+        } else {
+            None
+        }
+    }
+}
+
+/// Controls how `Slack` retries API calls that fail because of
+/// transient problems: transport errors (`HttpError`, `IoError`)
+/// and the `request_timeout`, `internal_error` and `fatal_error`
+/// API errors.
+///
+/// Each retry waits for an exponentially increasing backoff,
+/// jittered so that clients that failed together don't retry
+/// together. Only idempotent methods (see `SlackMethod::idempotent`)
+/// are retried unless `retry_writes` is set, since a write that
+/// failed in transit may already have taken effect.
+///
+/// By default, `Slack` does not retry failed calls.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of times a single API call is retried.
+    pub max_retries: u32,
+    /// Backoff before the first retry. It doubles with each retry after that.
+    pub initial_backoff: Duration,
+    /// Longest backoff before any retry.
+    pub max_backoff: Duration,
+    /// Retry calls to methods that aren't idempotent.
+    pub retry_writes: bool,
+}
+
+impl RetryPolicy {
+    /// Create a policy that retries each failed call to an idempotent
+    /// method at most `max_retries` times, backing off for `initial_backoff`
+    /// before the first retry and for no longer than `max_backoff` before any.
+    pub fn new(max_retries: u32, initial_backoff: Duration, max_backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: max_retries,
+            initial_backoff: initial_backoff,
+            max_backoff: max_backoff,
+            retry_writes: false,
+        }
+    }
+
+    /// Also retry calls to methods that aren't idempotent.
+    pub fn retry_writes(mut self, retry_writes: bool) -> RetryPolicy {
+        self.retry_writes = retry_writes;
+        self
+    }
+
+    /// Return how long to wait before retrying a failed call, or `None`
+    /// if the call should not be retried. `retries` is the number of times
+    /// the call has already been retried.
+    ///
+    /// The wait is chosen at random from between half and all of the backoff.
+    pub fn wait_before_retry(&self, retries: u32, idempotent: bool) -> Option<Duration> {
+        if retries >= self.max_retries || !(idempotent || self.retry_writes) {
+            return None;
+        }
+        let doublings = cmp::min(retries, MAX_BACKOFF_DOUBLINGS);
+        let backoff = cmp::min(millis(self.initial_backoff).saturating_mul(1 << doublings),
+                               millis(self.max_backoff));
+        Some(Duration::from_millis(backoff - jitter(backoff / 2)))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Retry idempotent calls at most three times, backing off
+    /// for 250ms before the first retry and at most 8s before any.
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(DEFAULT_MAX_RETRIES,
+                         Duration::from_millis(DEFAULT_INITIAL_BACKOFF_MILLIS),
+                         Duration::from_millis(DEFAULT_MAX_BACKOFF_MILLIS))
+    }
+}
+
+/// Return why `error` (if it's transient) is worth retrying. Only
+/// connections that were refused, reset or timed out are retried;
+/// other HTTP and I/O errors (invalid URLs, TLS failures, local file
+/// errors and so on) would fail again.
+pub fn transient_error(error: &Error) -> Option<String> {
+    match *error.kind() {
+        ErrorKind::HttpError(hyper::Error::Io(ref e)) if is_transient_io_error(e) => {
+            Some(format!("http error: {}", e))
+        }
+        ErrorKind::IoError(ref e) if is_transient_io_error(e) => Some(format!("io error: {}", e)),
+        ErrorKind::RequestTimeout => Some("api error request_timeout".to_string()),
+        ErrorKind::InternalError => Some("api error internal_error".to_string()),
+        ErrorKind::FatalError => Some("api error fatal_error".to_string()),
+        _ => None,
+    }
+}
+
+// Read timeouts are reported as `WouldBlock` on Unix and `TimedOut` on Windows.
+fn is_transient_io_error(error: &io::Error) -> bool {
+    match error.kind() {
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted |
+        io::ErrorKind::BrokenPipe | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => true,
+        _ => false,
+    }
+}
+
+/// Return `true` if the API error `error_string` is transient.
+pub fn is_transient_api_error(error_string: &str) -> bool {
+    match error_string {
+        "request_timeout" | "internal_error" | "fatal_error" => true,
+        _ => false,
+    }
+}
+
+/// Return `true` if calling `slack_method` has no side effects, judging
+/// by its name: methods that read (`*.info`, `*.list`, `*.history` and
+/// so on) can be called any number of times with the same result.
+pub fn is_idempotent(slack_method: &str) -> bool {
+    match slack_method.rsplit('.').next().unwrap_or("") {
+        "test" | "info" | "list" | "history" | "replies" | "get" | "getPresence" | "identity" => true,
+        _ => false,
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+// Random number in [0, max]. Jitter doesn't need a good random
+// number generator, so the sub-second part of the clock will do.
+fn jitter(max: u64) -> u64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    nanos as u64 % (max + 1)
+}
+
+/// Extract the wait duration from a response's `Retry-After` header.
+///
+/// Slack always specifies this value in seconds.
+pub fn retry_after(headers: &Headers) -> Option<Duration> {
+    headers.get_raw(RETRY_AFTER_HEADER)
+        .and_then(|values| values.first())
+        .and_then(|value| str::from_utf8(value).ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::time::Duration;
+
+    use hyper;
+    use url;
+
+    #[test]
+    fn back_off_exponentially_with_jitter() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(100), Duration::from_millis(300));
+        for &(retries, max) in &[(0, 100), (1, 200), (2, 300), (3, 300)] {
+            let wait = policy.wait_before_retry(retries, true).unwrap();
+            assert!(wait >= Duration::from_millis(max / 2) && wait <= Duration::from_millis(max),
+                    "retry {} waited {:?}",
+                    retries,
+                    wait);
+        }
+        assert_eq!(policy.wait_before_retry(4, true), None);
+    }
+
+    #[test]
+    fn retry_writes_only_if_allowed() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.wait_before_retry(0, false), None);
+        assert!(policy.retry_writes(true).wait_before_retry(0, false).is_some());
+    }
+
+    #[test]
+    fn retry_connection_failures() {
+        let reset: Error = io::Error::new(io::ErrorKind::ConnectionReset, "connection reset").into();
+        assert!(transient_error(&reset).is_some());
+        let timed_out: Error = hyper::Error::Io(io::Error::new(io::ErrorKind::TimedOut, "timed out")).into();
+        assert!(transient_error(&timed_out).is_some());
+        assert!(transient_error(&ErrorKind::InternalError.into()).is_some());
+    }
+
+    #[test]
+    fn dont_retry_permanent_failures() {
+        let errors: Vec<Error> =
+            vec![hyper::Error::Uri(url::ParseError::RelativeUrlWithoutBase).into(),
+                 hyper::Error::Ssl(Box::new(io::Error::new(io::ErrorKind::Other, "certificate verify failed"))).into(),
+                 hyper::Error::Status.into(),
+                 io::Error::new(io::ErrorKind::PermissionDenied, "cassette not writable").into(),
+                 ErrorKind::ChannelNotFound.into()];
+        for error in &errors {
+            assert_eq!(transient_error(error), None, "retried {:?}", error);
+        }
+    }
+
+    #[test]
+    fn recognize_idempotent_methods() {
+        assert!(is_idempotent("channels.list"));
+        assert!(is_idempotent("conversations.history"));
+        assert!(is_idempotent("api.test"));
+        assert!(!is_idempotent("chat.postMessage"));
+        assert!(!is_idempotent("channels.archive"));
+    }
+}
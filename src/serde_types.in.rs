@@ -14,7 +14,7 @@
 
 use serde_json;
 
-use base_types::{ChannelId, SlackUrl, UserId};
+use base_types::{ChannelId, SlackUrl, Timestamp, UserId};
 
 // TODO: does serde work with default trait
 // TODO: does serde work with extends? (i.e. a struct that implements a trait?)
@@ -34,6 +34,10 @@ pub struct ApiTestResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// Arguments supplied in the api.test call.
     pub args: Option<serde_json::Value>,
 }
@@ -49,6 +53,10 @@ pub struct ChannelsArchiveResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
 }
 
 /// Actual response received from an channels.create response.
@@ -62,6 +70,10 @@ pub struct ChannelsCreateResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// A `Channel` object representing the channel created by the `channels.create` call.
     pub channel: Option<Channel>,
 }
@@ -77,6 +89,10 @@ pub struct ChannelsInfoResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// A `Channel` object describing the requested channel.
     pub channel: Option<Channel>,
 }
@@ -92,6 +108,10 @@ pub struct ChannelsInviteResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// A `Channel` object describing the state of the channel after the invite succeeds.
     pub channel: Option<Channel>,
 }
@@ -107,6 +127,10 @@ pub struct ChannelsJoinResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// Only populated if the user has already joined the channel.
     pub already_in_channel: Option<bool>,
     /// If successful, the channel joined.
@@ -124,6 +148,10 @@ pub struct ChannelsLeaveResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// Only populated if the user was not in the channel.
     pub not_in_channel: Option<bool>,
 }
@@ -139,6 +167,10 @@ pub struct ChannelsListResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// List of channels in this team.
     pub channels: Vec<Channel>, // there'll be at least one: #general
 }
@@ -154,6 +186,10 @@ pub struct ChannelsMarkResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
 }
 
 /// Actual response received from a channels.rename call.
@@ -167,6 +203,10 @@ pub struct ChannelsRenameResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// New (limited) state of the channel.
     pub channel: Option<RenamedChannel>,
 }
@@ -197,6 +237,10 @@ pub struct ChannelsSetPurposeResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// New purpose for this channel.
     pub purpose: Option<String>,
 }
@@ -212,6 +256,10 @@ pub struct ChannelsSetTopicResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// New topic for this channel.
     pub topic: Option<String>,
 }
@@ -227,6 +275,10 @@ pub struct ChannelsUnarchiveResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
 }
 
 /// Actual response received from a chat.delete call.
@@ -240,10 +292,14 @@ pub struct ChatDeleteResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// Channel from which the message was deleted if the request was successful.
     pub channel: Option<ChannelId>,
     /// Timestamp of the deleted message if the request was successful.
-    pub ts: Option<f64>,
+    pub ts: Option<Timestamp>,
 }
 
 /// Actual response received from a chat.meMessage call.
@@ -257,10 +313,14 @@ pub struct ChatMeMessageResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     /// Channel to which the me-message was posted if the request was successful.
     pub channel: Option<ChannelId>,
     /// Timestamp of the me-message if the request was successful.
-    pub ts: Option<f64>,
+    pub ts: Option<Timestamp>,
 }
 
 // Actual response received from a chat.postMessage call.
@@ -274,14 +334,62 @@ pub struct ChatPostMessageResponse {
     pub error: Option<String>,
     /// Only populated if there is a warning.
     pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
     // Time when the message was posted to the channel.
-    pub ts: Option<f64>,
+    pub ts: Option<Timestamp>,
     // Channel to which the message was posted.
     pub channel: Option<ChannelId>,
     // Final message content as posted to the channel.
     pub message: Option<Message>,
 }
 
+/// Actual response received from a conversations.history call.
+///
+/// See [Slack conversations.history Method (Response)](https://api.slack.com/methods/conversations.history "Slack conversations.history Method (Response)")
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConversationsHistoryResponse {
+    /// `true` if the request was successful, `false` otherwise.
+    pub ok: bool,
+    /// Only populated if there is an error.
+    pub error: Option<String>,
+    /// Only populated if there is a warning.
+    pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
+    /// Messages in the channel, newest first, if the request was successful.
+    pub messages: Option<Vec<HistoryMessage>>,
+    /// `true` if there are older messages than those returned.
+    pub has_more: Option<bool>,
+}
+
+/// Actual response received from an oauth.v2.access call.
+///
+/// See [Slack oauth.v2.access Method (Response)](https://api.slack.com/methods/oauth.v2.access "Slack oauth.v2.access Method (Response)")
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OAuthV2AccessResponse {
+    /// `true` if the request was successful, `false` otherwise.
+    pub ok: bool,
+    /// Only populated if there is an error.
+    pub error: Option<String>,
+    /// Only populated if there is a warning.
+    pub warning: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::needed_scopes`.
+    pub needed: Option<String>,
+    /// Only populated if the error is `missing_scope`; see `ApiResponse::provided_scopes`.
+    pub provided: Option<String>,
+    /// New access token.
+    pub access_token: Option<String>,
+    /// New refresh token, to be used for the next refresh.
+    pub refresh_token: Option<String>,
+    /// Number of seconds until the new access token expires.
+    pub expires_in: Option<u64>,
+}
+
 //
 // Object definitions
 //
@@ -289,12 +397,14 @@ pub struct ChatPostMessageResponse {
 /// Message to be posted via `chat.postMessage`.
 ///
 /// See [Slack chat.postMessage Method (Request))](https://api.slack.com/methods/chat.postMessage "Slack chat.postMessage Method (Request)")
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Message {
     /// Message text. Required, unless `attachments` are provided.
     /// You may use both `text` and `attachments`.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub text: Option<String>,
     /// Attachments to be included in the message
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub attachments: Option<Vec<Attachment>>,
     /// Set message parsing behavior.
     ///
@@ -306,37 +416,60 @@ pub struct Message {
     /// * If set to `None` non-linked URLs are linkified,
     /// but usernames and channels are not (this can be turned
     /// on by setting `link_names` below).
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub parse: Option<MessageParseBehavior>,
     /// Set to `1` if user and channel names
     /// should automatically be linked within the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub link_names: Option<LinkNames>,
     /// Create an attachment for each text-based link in the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub unfurl_links: Option<bool>,
     /// Create an attachment for each media link in the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub unfurl_media: Option<bool>,
     /// Set the bot username.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
     /// Post the message as the user specified in `username`.
     /// If this value is `false` then the user posting the message
     /// will be inferred by the server.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub as_user: Option<bool>,
     /// URL to an image to use as the icon for this message. Must be used with
     /// `as_user` set to `false`, otherwise ignored.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub icon_url: Option<SlackUrl>,
     // Emoji to use as the icon for this message. Overrides `icon_url`. Must be
     // used with `as_user` set to `false`, otherwise ignored.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub icon_emoji: Option<String>,
     /// Set to `true` to enable formatting of a message sent by
     /// a bot, `false` otherwise. The default is `true`, so message
     /// text in a bot-sent message is always markdown-formatted.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mrkdwn: Option<bool>,
+    /// Structured data attached to the message, which isn't displayed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MessageMetadata>,
+}
+
+/// Event with which a message is tagged, for apps to read back.
+///
+/// See [Using message metadata](https://api.slack.com/metadata/using "Using message metadata")
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MessageMetadata {
+    /// Name of the event, for example, `task_created`.
+    pub event_type: String,
+    /// Data describing the event.
+    pub event_payload: serde_json::Map<String, serde_json::Value>,
 }
 
 /// Control how the Slack server will parse
 /// and auto-link messages sent by this client.
 ///
 /// See [Basic message formatting (Parsing modes)](https://api.slack.com/docs/message-formatting#linking_to_urls "Basic message formatting (Parsing modes)")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Debug)]
 pub enum MessageParseBehavior {
     /// Parse the supplied message and auto-link
     /// any user names (identified by a preceding `@`)
@@ -357,7 +490,7 @@ pub enum MessageParseBehavior {
 /// is sent.
 ///
 /// See [Basic message formatting (Parsing modes)](https://api.slack.com/docs/message-formatting#linking_to_urls "Basic message formatting (Parsing modes)")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Debug)]
 pub enum LinkNames {
     /// Enable auto-linking channel and user names in a message.
     Enable,
@@ -370,47 +503,60 @@ pub enum LinkNames {
 /// of the Slack Web API JSON.
 ///
 /// See [Attaching content and links to messages](https://api.slack.com/docs/message-attachments "Attaching content and links to messages")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Attachment {
     /// Plain-text summary of the attachment.
     pub fallback: String,
     /// Attachment color.
     /// Hex-color code or "good", "warning", "danger".
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub color: Option<Color>,
     /// Optional text that appears above the attachment block.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pretext: Option<String>,
     /// Name of the person that created the attachment.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub author_name: Option<String>,
     /// Link to the author's webpage. This will hyperlink the author's
     /// name, and will only take effect if `author_name` is defined.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub author_link: Option<SlackUrl>,
     /// Link to a small 16x16px image to the left of the `author_name`
     /// text. This only takes effect if `author_name` is defined.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub author_icon: Option<SlackUrl>,
     /// Larger, bold text on top of the message attachment.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
     /// Link that will be used to hyperlink `title` if it exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub title_link: Option<SlackUrl>,
     /// Main text in the attachment. It can contain standard message markup. The
     /// content automatically collapses if it contains 700+ characters or 5+ linebreaks,
     /// and will display a "Show more..." link to expand the content.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub text: Option<String>,
     /// Fields to display in a table in the attachment.
     /// Fields have a title and a value.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fields: Option<Vec<AttachmentFields>>,
     /// URL to an image to be displayed inside a message attachment.
     /// Large images will be resized to a maximum width of 400px or a
     /// maximum height of 500px while maintaining the same aspect ratio.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub image_url: Option<SlackUrl>,
     /// URL to an image that will be displayed as a thumbnail to the right
     /// of a message attachment. The thumbnail's largest dimension will be
     /// resided to a maximum of 75px while maintaining the aspect ratio.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub thumb_url: Option<SlackUrl>,
     /// Text at the bottom of the attachment. Limited to 300 characters and
     /// may be truncated for users with smaller displays.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub footer: Option<String>,
     /// Url to an image to be displayed next to the footer text. Will be
     /// resized to 16px by 16px. Only takes effect if `footer` is defined.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub footer_icon: Option<SlackUrl>,
     /// Epoch time associated with this attachment. Used to indicate that
     /// the attachment refers to an event as a specific time, and will
@@ -419,13 +565,14 @@ pub struct Attachment {
     /// List of attachment fields (`text`, `pretext`, etc.) to be formatted
     /// using markdown in bot-sent messages. By default, attachment fields
     /// in bot-sent messages are *not* markdown-formatted.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mrkdwn_in: Option<Vec<String>>,
 }
 
 /// Color with which to highlight a message attachment.
 ///
 /// See [Attaching content and links to messages](https://api.slack.com/docs/message-attachments "Attaching content and links to messages")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Debug)]
 pub enum Color {
     /// Indicates a normal-priority message.
     Good,
@@ -440,7 +587,7 @@ pub enum Color {
 /// Attachment fields that can be displayed in an `Attachment`.
 ///
 /// See [Attaching content and links to messages](https://api.slack.com/docs/message-attachments "Attaching content and links to messages")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AttachmentFields {
     /// Bold heading above the value text. This cannot
     /// contain markup and will be automatically escaped.
@@ -452,6 +599,21 @@ pub struct AttachmentFields {
     pub short: bool,
 }
 
+/// Message in a channel's history.
+///
+/// See [Slack conversations.history Method (Response)](https://api.slack.com/methods/conversations.history "Slack conversations.history Method (Response)")
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryMessage {
+    /// Message timestamp, which identifies the message within its channel.
+    pub ts: Timestamp,
+    /// Unique ID of the user who posted the message, if posted by a user.
+    pub user: Option<UserId>,
+    /// Message text, if any.
+    pub text: Option<String>,
+    /// Message metadata, if any.
+    pub metadata: Option<MessageMetadata>,
+}
+
 /// Represents a single Slack channel.
 /// All fields in this struct are exact analogs of
 /// the Slack Web API JSON.
@@ -462,7 +624,7 @@ pub struct AttachmentFields {
 /// See [Slack channels.join Method (Response)](https://api.slack.com/methods/channels.join "Slack channels.join Method (Response)")
 ///
 /// See [Slack Object Types: Channel](https://api.slack.com/types/channel "Slack Object Types: Channel")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Channel {
     /// Unique ID of the channel.
     pub id: ChannelId,
@@ -487,7 +649,7 @@ pub struct Channel {
     /// `false` otherwise.
     pub is_member: Option<bool>,
     /// Timestamp of the last message the user making the API call read.
-    pub last_read: Option<f64>,
+    pub last_read: Option<Timestamp>,
     /// Count of all visible messages that the user making the API call has yet to read.
     pub unread_count: Option<u32>,
     /// Count of messages that the user making the API call
@@ -500,12 +662,12 @@ pub struct Channel {
 /// the Slack Web API JSON.
 ///
 /// See [Slack Object Types: Channel](https://api.slack.com/types/channel "Slack Object Types: Channel")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Topic {
     /// Topic text.
     value: String,
-    /// User who set the topic.
-    creator: UserId,
+    /// User who set the topic, or empty if it was never set.
+    creator: String,
     /// Epoch time at which the topic was last set.
     last_set: u32,
 }
@@ -515,12 +677,12 @@ pub struct Topic {
 /// the Slack Web API JSON.
 ///
 /// See [Slack Object Types: Channel](https://api.slack.com/types/channel "Slack Object Types: Channel")
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Purpose {
     /// Purpose text.
     value: String,
-    /// User who set the purpose.
-    creator: UserId,
+    /// User who set the purpose, or empty if it was never set.
+    creator: String,
     /// Epoch time at which the purpose was last set.
     last_set: u32,
 }
@@ -14,56 +14,306 @@
 
 use std::collections::HashMap;
 use std::convert::From;
-use std::io::Read;
-
-use hyper::client::{Client, RequestBuilder};
-use hyper::header::{ContentLength, ContentType};
-use hyper::client::response::Response;
+use std::io::BufReader;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hyper::header::{Authorization, Bearer, Headers, UserAgent};
+use hyper::method::Method;
+use hyper::status::StatusCode;
 use hyper::Url;
 
-use base_types::{ChannelId, UserId};
-use chat;
+use auth::{AccessToken, TokenPlacement, TokenProvider};
+use base_types::{ChannelId, Timestamp, UserId};
+use cache::ResponseCache;
+use drift;
 use errors::*;
+use methods::*;
+use metrics::{CallMetrics, MetricsObserver};
+use rate_limit::RateLimiter;
+use retry::{self, RateLimitPolicy, RetryPolicy};
 use serde_types::*;
+use transport::{HttpConfig, HttpRequest, HttpResponse, HyperTransport, RequestEncoding, Transport};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json;
 
 static SLACK_BASE_API_URL: &'static str = "https://slack.com/api";
-const DEFAULT_RESPONSE_CONTENT_LENGTH: usize = 256;
+static DEFAULT_USER_AGENT: &'static str = concat!("slacker/", env!("CARGO_PKG_VERSION"));
+static OAUTH_SCOPES_HEADER: &'static str = "X-OAuth-Scopes";
+static ACCEPTED_OAUTH_SCOPES_HEADER: &'static str = "X-Accepted-OAuth-Scopes";
+static IDEMPOTENT_POST_EVENT_TYPE: &'static str = "slacker_idempotent_post";
+static IDEMPOTENCY_KEY_FIELD: &'static str = "slacker_idempotency_key";
+// messages searched for an idempotency key
+const IDEMPOTENCY_HISTORY_LIMIT: u32 = 100;
 
-macro_rules! slack_result {
-    ($x:expr) => {
-        if $x.ok {
-            Ok($x)
-        } else {
-            Err(from_api_error_string(&$x.error.unwrap()))
-        }
-    };
-    ($x:expr, ()) => {
-        if $x.ok {
-            Ok(())
-        } else {
-            Err(from_api_error_string(&$x.error.unwrap()))
-        }
-    };
+pub struct Slack {
+    token_provider: Box<TokenProvider>,
+    token_placement: TokenPlacement,
+    base_url: String,
+    user_agent: String,
+    transport: Box<Transport>,
+    rate_limit_policy: Option<RateLimitPolicy>,
+    retry_policy: Option<RetryPolicy>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    json_requests: bool,
+    warning_handler: Box<Fn(&str, &str) + Send + Sync>,
+    unknown_field_handler: Option<Box<Fn(&str, &str) + Send + Sync>>,
+    scope_preflight: bool,
+    cache: ResponseCache,
+    metrics_observer: Option<Box<MetricsObserver>>,
+    granted_scopes: Mutex<Option<Vec<String>>>,
+    accepted_scopes: Mutex<HashMap<String, Vec<String>>>,
 }
 
-pub struct Slack {
-    access_token: String,
-    client: Client,
+// Last HTTP status and number of retries of an API call.
+#[derive(Default)]
+struct CallStats {
+    status: Option<StatusCode>,
+    retries: u32,
+}
+
+/// Configures and creates a `Slack` instance.
+///
+/// Use this when the defaults used by `Slack::new` aren't
+/// appropriate, for example, to make API calls against
+/// a local stand-in for the Slack Web API or via a proxy.
+pub struct SlackBuilder {
+    token_provider: Box<TokenProvider>,
+    token_placement: TokenPlacement,
+    base_url: String,
+    user_agent: String,
+    http_config: HttpConfig,
+    transport: Option<Box<Transport>>,
+    rate_limit_policy: Option<RateLimitPolicy>,
+    retry_policy: Option<RetryPolicy>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    json_requests: bool,
+    warning_handler: Box<Fn(&str, &str) + Send + Sync>,
+    unknown_field_handler: Option<Box<Fn(&str, &str) + Send + Sync>>,
+    scope_preflight: bool,
+    cache_ttls: HashMap<String, Duration>,
+    metrics_observer: Option<Box<MetricsObserver>>,
+}
+
+impl SlackBuilder {
+    /// Create a builder for a `Slack` instance that makes API calls with `access_token`.
+    pub fn new(access_token: &str) -> SlackBuilder {
+        SlackBuilder {
+            token_provider: Box::new(AccessToken::new(access_token)),
+            token_placement: TokenPlacement::default(),
+            base_url: SLACK_BASE_API_URL.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            http_config: HttpConfig::default(),
+            transport: None,
+            rate_limit_policy: None,
+            retry_policy: None,
+            rate_limiter: None,
+            json_requests: false,
+            warning_handler: Box::new(log_warning),
+            unknown_field_handler: None,
+            scope_preflight: true,
+            cache_ttls: HashMap::new(),
+            metrics_observer: None,
+        }
+    }
+
+    /// Set the URL relative to which all API method endpoints are resolved.
+    /// Defaults to `https://slack.com/api`.
+    pub fn base_url(mut self, base_url: &str) -> SlackBuilder {
+        self.base_url = base_url.trim_right_matches('/').to_string();
+        self
+    }
+
+    /// Set how the access token is sent in each API call.
+    /// Defaults to `TokenPlacement::AuthorizationHeader`.
+    pub fn token_placement(mut self, token_placement: TokenPlacement) -> SlackBuilder {
+        self.token_placement = token_placement;
+        self
+    }
+
+    /// Set the `User-Agent` header sent with each API call.
+    /// Defaults to `slacker/<version>`.
+    pub fn user_agent(mut self, user_agent: &str) -> SlackBuilder {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Fail API calls if no data is received from the server for `timeout`.
+    /// API calls wait indefinitely by default.
+    pub fn read_timeout(mut self, timeout: Duration) -> SlackBuilder {
+        self.http_config.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail API calls if data cannot be sent to the server for `timeout`.
+    /// API calls wait indefinitely by default.
+    pub fn write_timeout(mut self, timeout: Duration) -> SlackBuilder {
+        self.http_config.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max_idle` idle connections open to the server.
+    /// Ignored if an HTTP proxy is used.
+    pub fn max_idle_connections(mut self, max_idle: usize) -> SlackBuilder {
+        self.http_config.max_idle_connections = Some(max_idle);
+        self
+    }
+
+    /// Make all API calls through the HTTP proxy at `host`:`port`.
+    pub fn http_proxy(mut self, host: &str, port: u16) -> SlackBuilder {
+        self.http_config.proxy = Some((host.to_string(), port));
+        self
+    }
+
+    /// Make all API calls via `transport` instead of the default `HyperTransport`.
+    /// Timeouts, connection pool and proxy settings are *not* applied to `transport`.
+    pub fn transport<T>(mut self, transport: T) -> SlackBuilder where T: Transport + 'static {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+
+    /// Wait and retry API calls rejected because of rate limits,
+    /// as allowed by `policy`. Rate-limited calls are not retried by default.
+    pub fn rate_limit_policy(mut self, policy: RateLimitPolicy) -> SlackBuilder {
+        self.rate_limit_policy = Some(policy);
+        self
+    }
+
+    /// Retry API calls that fail because of transient errors, as allowed
+    /// by `policy`. Failed calls are not retried by default.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> SlackBuilder {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Pace API calls client-side using `rate_limiter`. Share a single
+    /// `RateLimiter` between all `Slack` instances that use the same
+    /// access token. API calls are not paced by default.
+    pub fn rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> SlackBuilder {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Send `application/json` bodies to the API methods that accept them
+    /// (for example, `chat.postMessage`) instead of form-encoded bodies.
+    /// This has no effect if the access token is sent as a query parameter.
+    pub fn json_requests(mut self, json_requests: bool) -> SlackBuilder {
+        self.json_requests = json_requests;
+        self
+    }
+
+    /// Call `handler` with the method name and warning (for example,
+    /// `missing_charset`) whenever an API response has a `warning` field.
+    /// Warnings flag problems that Slack may later turn into errors.
+    /// By default, they're logged at the `warn` level.
+    pub fn warning_handler<F>(mut self, handler: F) -> SlackBuilder
+        where F: Fn(&str, &str) + Send + Sync + 'static
+    {
+        self.warning_handler = Box::new(handler);
+        self
+    }
+
+    /// Call `handler` with the method name and field path (for example,
+    /// `channel.is_shared`) for each field of an API response that
+    /// isn't in the type it's deserialized into, to detect changes to
+    /// the Slack Web API that this crate doesn't know about yet.
+    /// Responses are parsed twice while this is set, so it's best
+    /// left off in production. Unknown fields are ignored by default.
+    pub fn unknown_field_handler<F>(mut self, handler: F) -> SlackBuilder
+        where F: Fn(&str, &str) + Send + Sync + 'static
+    {
+        self.unknown_field_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Check, before each API call, that the access token has one of the
+    /// OAuth scopes the method accepts, and fail with `MissingScope` without
+    /// making the call if it doesn't. Scopes are only checked once they've been
+    /// reported by the `X-OAuth-Scopes` header of an earlier response. This
+    /// is enabled by default.
+    pub fn scope_preflight(mut self, scope_preflight: bool) -> SlackBuilder {
+        self.scope_preflight = scope_preflight;
+        self
+    }
+
+    /// Cache the responses of `slack_method` for `ttl`. Only the
+    /// `channels.info` and `channels.list` responses returned by
+    /// `Slack::channels_info` and `Slack::channels_list` are cached;
+    /// TTLs for other methods are ignored. Cached channels are dropped
+    /// whenever they're changed through the same `Slack` instance.
+    /// Nothing is cached by default.
+    pub fn cache_ttl(mut self, slack_method: &str, ttl: Duration) -> SlackBuilder {
+        self.cache_ttls.insert(slack_method.to_string(), ttl);
+        self
+    }
+
+    /// Report the outcome of each API call to `observer`, for example,
+    /// an `Arc<InMemoryMetrics>`. Outcomes aren't reported by default.
+    pub fn metrics_observer<O>(mut self, observer: O) -> SlackBuilder where O: MetricsObserver + 'static {
+        self.metrics_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Get the access token for each API call from `token_provider`
+    /// instead of using the one passed to `SlackBuilder::new`. If
+    /// Slack responds that the token has expired, the provider is
+    /// asked to refresh it and the call is retried once.
+    pub fn token_provider<P>(mut self, token_provider: P) -> SlackBuilder where P: TokenProvider + 'static {
+        self.token_provider = Box::new(token_provider);
+        self
+    }
+
+    /// Create the configured `Slack` instance.
+    ///
+    /// Fails if the base URL is not a valid URL.
+    pub fn build(self) -> Result<Slack> {
+        try!(Url::parse(&self.base_url));
+        Ok(Slack {
+            token_provider: self.token_provider,
+            token_placement: self.token_placement,
+            base_url: self.base_url,
+            user_agent: self.user_agent,
+            transport: match self.transport {
+                Some(transport) => transport,
+                None => Box::new(HyperTransport::with_config(&self.http_config)),
+            },
+            rate_limit_policy: self.rate_limit_policy,
+            retry_policy: self.retry_policy,
+            rate_limiter: self.rate_limiter,
+            json_requests: self.json_requests,
+            warning_handler: self.warning_handler,
+            unknown_field_handler: self.unknown_field_handler,
+            scope_preflight: self.scope_preflight,
+            cache: ResponseCache::new(&self.cache_ttls),
+            metrics_observer: self.metrics_observer,
+            granted_scopes: Mutex::new(None),
+            accepted_scopes: Mutex::new(HashMap::new()),
+        })
+    }
 }
 
 // TODO: split groups of slack calls into different files
-// TODO: expose two versions of the send/recv API: one that returns a message type and another that returns a JsonValue
 
 impl Slack {
 
     pub fn new(access_token: &str) -> Slack {
-        Slack {
-            access_token: access_token.to_string(),
-            client: Client::new(),
-        }
+        Slack::with_transport(access_token, HyperTransport::new())
+    }
+
+    /// Create a `Slack` instance that makes all its API calls via `transport`.
+    pub fn with_transport<T>(access_token: &str, transport: T) -> Slack where T: Transport + 'static {
+        SlackBuilder::new(access_token)
+            .transport(transport)
+            .build()
+            .expect("default slack api base url is valid")
+    }
+
+    /// Create a `SlackBuilder` to configure a `Slack` instance before it's created.
+    pub fn builder(access_token: &str) -> SlackBuilder {
+        SlackBuilder::new(access_token)
     }
 
     //
@@ -72,14 +322,10 @@ impl Slack {
 
     // TODO: allow the value in the map to be any string-able type
     pub fn api_test(&self, arguments: HashMap<&str, &str>, error: Option<&str>) -> Result<ApiTestResponse> {
-        let mut api_url = try!(self.api_url("api.test"));
-        api_url.query_pairs_mut().extend_pairs(arguments.iter());
-        error.map(|e| {api_url.query_pairs_mut().append_pair("error", e); () });
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ApiTestResponse>(request));
-        slack_result!(deserialized)
+        let mut arguments: HashMap<String, String> =
+            arguments.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        error.map(|e| arguments.insert("error".to_string(), e.to_string()));
+        self.call::<ApiTest>(&arguments)
     }
 
     //
@@ -87,61 +333,38 @@ impl Slack {
     //
 
     pub fn channels_archive(&self, channel: &ChannelId) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.archive"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsArchiveResponse>(request));
-        slack_result!(deserialized, ())
+        let result = self.call::<ChannelsArchive>(channel);
+        self.cache.invalidate_channel(channel);
+        result.map(|_| ())
     }
 
     pub fn channels_create(&self, channel_name: &str) -> Result<Channel> {
-        let mut api_url = try!(self.api_url("channels.create"));
-        api_url.query_pairs_mut().append_pair("name", channel_name);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsCreateResponse>(request));
-        slack_result!(deserialized).map(|d| d.channel.unwrap())
+        let result = self.call::<ChannelsCreate>(channel_name);
+        self.cache.invalidate_channel_lists();
+        result.map(|d| d.channel.unwrap())
     }
 
     pub fn channels_info(&self, channel: &ChannelId) -> Result<Channel> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.info"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsInfoResponse>(request));
-        slack_result!(deserialized).map(|d| d.channel.unwrap())
+        if let Some(cached) = self.cache.channel(channel) {
+            return Ok(cached);
+        }
+        let generation = self.cache.generation();
+        let info = try!(self.call::<ChannelsInfo>(channel)).channel.unwrap();
+        self.cache.insert_channel(channel, &info, generation);
+        Ok(info)
     }
 
     pub fn channels_invite(&self, channel: &ChannelId, user: &UserId) -> Result<Channel> {
-        try!(validate_user_id(user));
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.invite"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        api_url.query_pairs_mut().append_pair("user", user);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsInviteResponse>(request));
-        slack_result!(deserialized).map(|d| d.channel.unwrap())
+        let result = self.call::<ChannelsInvite>(&(channel.clone(), user.clone()));
+        self.cache.invalidate_channel(channel);
+        result.map(|d| d.channel.unwrap())
     }
 
     pub fn channels_join(&self, channel_name: &str) -> Result<()> {
-        let mut api_url = try!(self.api_url("channels.join"));
-        api_url.query_pairs_mut().append_pair("name", channel_name);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsJoinResponse>(request));
-        slack_result!(deserialized, ())
+        let result = self.call::<ChannelsJoin>(channel_name);
+        // the channel is named, so its cached info can't be found
+        self.cache.clear();
+        result.map(|_| ())
     }
 
     pub fn channels_kick(&self) -> Result<()> {
@@ -149,138 +372,120 @@ impl Slack {
     }
 
     pub fn channels_leave(&self, channel: &ChannelId) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.leave"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsLeaveResponse>(request));
-        slack_result!(deserialized, ())
+        let result = self.call::<ChannelsLeave>(channel);
+        self.cache.invalidate_channel(channel);
+        result.map(|_| ())
     }
 
     pub fn channels_list(&self, exclude_archived: bool) -> Result<Vec<Channel>> {
-        let mut api_url = try!(self.api_url("channels.list"));
-        api_url.query_pairs_mut().append_pair("exclude_archived", &exclude_archived.to_string());
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsListResponse>(request));
-        slack_result!(deserialized).map(|d| d.channels)
+        if let Some(cached) = self.cache.channel_list(exclude_archived) {
+            return Ok(cached);
+        }
+        let generation = self.cache.generation();
+        let channels = try!(self.call::<ChannelsList>(&exclude_archived)).channels;
+        self.cache.insert_channel_list(exclude_archived, &channels, generation);
+        Ok(channels)
     }
 
-    pub fn channels_mark(&self, channel: &ChannelId, timestamp: f64) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.mark"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        api_url.query_pairs_mut().append_pair("ts", &timestamp.to_string());
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsMarkResponse>(request));
-        slack_result!(deserialized, ())
+    pub fn channels_mark(&self, channel: &ChannelId, timestamp: &Timestamp) -> Result<()> {
+        let result = self.call::<ChannelsMark>(&(channel.clone(), timestamp.clone()));
+        self.cache.invalidate_channel(channel);
+        result.map(|_| ())
     }
 
     pub fn channels_rename(&self, channel: &ChannelId, new_name: &str) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.rename"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        api_url.query_pairs_mut().append_pair("name", new_name);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsRenameResponse>(request));
-        slack_result!(deserialized, ())
+        let result = self.call::<ChannelsRename>(&(channel.clone(), new_name.to_string()));
+        self.cache.invalidate_channel(channel);
+        result.map(|_| ())
     }
 
     pub fn channels_set_purpose(&self, channel: &ChannelId, new_purpose: &str) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.setPurpose"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        api_url.query_pairs_mut().append_pair("purpose", new_purpose);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsSetPurposeResponse>(request));
-        slack_result!(deserialized, ())
+        let result = self.call::<ChannelsSetPurpose>(&(channel.clone(), new_purpose.to_string()));
+        self.cache.invalidate_channel(channel);
+        result.map(|_| ())
     }
 
     pub fn channels_set_topic(&self, channel: &ChannelId, new_topic: &str) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.setTopic"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        api_url.query_pairs_mut().append_pair("topic", new_topic);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsSetTopicResponse>(request));
-        slack_result!(deserialized, ())
+        let result = self.call::<ChannelsSetTopic>(&(channel.clone(), new_topic.to_string()));
+        self.cache.invalidate_channel(channel);
+        result.map(|_| ())
     }
 
     pub fn channels_unarchive(&self, channel: &ChannelId) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("channels.unarchive"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChannelsUnarchiveResponse>(request));
-        slack_result!(deserialized, ())
+        let result = self.call::<ChannelsUnarchive>(channel);
+        self.cache.invalidate_channel(channel);
+        result.map(|_| ())
     }
 
     //
     // chat
     //
 
-    pub fn chat_delete(&self, channel: &ChannelId, message_timestamp: f64, as_user: Option<bool>) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("chat.delete"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        api_url.query_pairs_mut().append_pair("ts", &message_timestamp.to_string());
-        as_user.map(|b| { api_url.query_pairs_mut().append_pair("as_user", &b.to_string()); () });
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChatDeleteResponse>(request));
-        slack_result!(deserialized, ())
+    pub fn chat_delete(&self, channel: &ChannelId, message_timestamp: &Timestamp, as_user: Option<bool>) -> Result<()> {
+        self.call::<ChatDelete>(&(channel.clone(), message_timestamp.clone(), as_user)).map(|_| ())
     }
 
     // TODO: allow the user to specify a channel name as well
     pub fn chat_me_message(&self, channel: &ChannelId, message_text: &str) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("chat.meMessage"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        api_url.query_pairs_mut().append_pair("text", message_text);
-        let api_url = api_url;
-
-        let request = self.client.get(api_url);
-        let deserialized = try!(Slack::send::<ChatMeMessageResponse>(request));
-        slack_result!(deserialized, ())
+        self.call::<ChatMeMessage>(&(channel.clone(), message_text.to_string())).map(|_| ())
     }
 
     pub fn chat_post_message(&self, channel: &ChannelId, message: &Message) -> Result<()> {
-        try!(validate_channel_id(channel));
-
-        let mut api_url = try!(self.api_url("chat.postMessage"));
-        api_url.query_pairs_mut().append_pair("channel", channel);
-        let api_url = api_url;
-
-        let message_string = try!(chat::encode_message(message));
-        let request = self.client // interesting: can't build request body without url
-            .post(api_url)
-            .header(ContentType::form_url_encoded())
-            .body(&message_string);
-
-        let deserialized = try!(Slack::send::<ChatPostMessageResponse>(request));
-        slack_result!(deserialized, ())
+        self.call::<ChatPostMessage>(&(channel.clone(), message.clone())).map(|_| ())
+    }
+
+    /// Post `message` to `channel` at most once, even if the call is retried,
+    /// and return the message's timestamp.
+    ///
+    /// The message is tagged with `key` in its metadata. `key` must be unique
+    /// to the message, and the same each time posting it is attempted. If the
+    /// call fails in a way that leaves it unclear whether the message was posted
+    /// (a transport error, `request_timeout`, `internal_error` or `fatal_error`),
+    /// the channel's recent history is searched for a message tagged with `key`
+    /// before it's posted again. Posting is retried as allowed by the retry
+    /// policy, or `RetryPolicy::default()` if none was set.
+    pub fn chat_post_message_idempotent(&self, channel: &ChannelId, message: &Message, key: &str) -> Result<Timestamp> {
+        let mut message = message.clone();
+        let mut metadata = message.metadata.take().unwrap_or_else(|| {
+            MessageMetadata { event_type: IDEMPOTENT_POST_EVENT_TYPE.to_string(), ..Default::default() }
+        });
+        metadata.event_payload.insert(IDEMPOTENCY_KEY_FIELD.to_string(), serde_json::Value::String(key.to_string()));
+        message.metadata = Some(metadata);
+        let args = (channel.clone(), message);
+
+        let retry_policy = self.retry_policy.unwrap_or_default();
+        let mut retries = 0;
+        loop {
+            let error = match self.call_with::<ChatPostMessage>(&args, None) {
+                Ok(response) => return response.ts.ok_or_else(|| ErrorKind::Unknown("no_ts".to_string()).into()),
+                Err(error) => error,
+            };
+            let reason = match retry::transient_error(&error) {
+                Some(reason) => reason,
+                None => return Err(error),
+            };
+            // if the history can't be searched it isn't safe to post again
+            match self.find_tagged_message(channel, key) {
+                Ok(Some(ts)) => {
+                    debug!("slack api call chat.postMessage: {}; found message {} posted with key {}", reason, ts, key);
+                    return Ok(ts);
+                }
+                Ok(None) => (),
+                Err(_) => return Err(error),
+            }
+            match retry_policy.wait_before_retry(retries, true) {
+                Some(wait) => {
+                    retries += 1;
+                    warn!("slack api call chat.postMessage: {}; message with key {} not found; retry {} after {:?}",
+                          reason,
+                          key,
+                          retries,
+                          wait);
+                    thread::sleep(wait);
+                }
+                None => return Err(error),
+            }
+        }
     }
 
     pub fn chat_update(&self) -> Result<()> {
@@ -380,35 +585,378 @@ impl Slack {
     //
 
     pub fn api_url(&self, slack_method: &str) -> Result<Url> {
-        let mut url = try!(Url::parse(&format!("{}/{}", SLACK_BASE_API_URL, slack_method)));
-        url.query_pairs_mut().append_pair("token", &self.access_token);
-        Ok(url)
+        Url::parse(&format!("{}/{}", self.base_url, slack_method)).map_err(From::from)
+    }
+
+    pub fn send<T>(&self, request: &HttpRequest) -> Result<T> where T: Deserialize {
+        let slack_method = request.url.path_segments().and_then(|s| s.last()).unwrap_or("").to_string();
+        let access_token = try!(self.token_provider.access_token());
+        let mut response = try!(self.execute(&slack_method, None, &access_token, request, &mut CallStats::default()));
+        deserialize(&mut response)
+    }
+
+    /// Call the API method `M` with `args`, returning its full response.
+    ///
+    /// This is how every typed method of `Slack` is implemented,
+    /// and can be used to call methods that this crate doesn't
+    /// wrap by implementing `SlackMethod` for them.
+    pub fn call<M>(&self, args: &M::Args) -> Result<M::Response> where M: SlackMethod {
+        self.call_with::<M>(args, self.retry_policy)
+    }
+
+    // Call the API method `M` with `args`, retrying as allowed by
+    // `retry_policy` instead of the one set with `SlackBuilder`.
+    fn call_with<M>(&self, args: &M::Args, retry_policy: Option<RetryPolicy>) -> Result<M::Response>
+        where M: SlackMethod
+    {
+        let arguments = try!(M::arguments(args));
+        let arguments: Vec<(&str, &str)> = arguments.iter().map(|&(ref k, ref v)| (k.as_str(), v.as_str())).collect();
+        let encoding = match M::http_method() {
+            Method::Get => RequestEncoding::Query,
+            _ => self.request_encoding(M::name()),
+        };
+        let api_url = try!(self.api_url(M::name()));
+        let request = match (encoding, M::json_body(args)) {
+            (RequestEncoding::Json, Some(body)) => HttpRequest::json(api_url, try!(body)),
+            (encoding, _) => try!(encoding.encode(api_url, &arguments)),
+        };
+        self.invoke(M::name(), M::accepted_scopes(), retry_policy, M::idempotent(), &arguments, &request)
+    }
+
+    /// Call any Slack API method, including those this crate doesn't
+    /// wrap, and return its response as untyped JSON.
+    ///
+    /// The call is authenticated, rate-limited and encoded like those
+    /// made by the typed methods, and fails with the corresponding
+    /// `ErrorKind` if Slack responds with `"ok": false`. It's retried
+    /// as if `slack_method` were a `SlackMethod` with the default
+    /// `idempotent`.
+    pub fn call_raw(&self, slack_method: &str, arguments: &[(&str, &str)]) -> Result<serde_json::Value> {
+        let api_url = try!(self.api_url(slack_method));
+        let request = try!(self.request_encoding(slack_method).encode(api_url, arguments));
+        let idempotent = retry::is_idempotent(slack_method);
+        self.invoke(slack_method, &[], self.retry_policy, idempotent, arguments, &request)
+    }
+
+    /// Drop every cached response that includes `channel`, so that
+    /// the next lookup fetches it from Slack. Use this when the channel
+    /// is changed other than through this `Slack` instance.
+    pub fn invalidate_channel(&self, channel: &ChannelId) {
+        self.cache.invalidate_channel(channel);
+    }
+
+    /// Drop every cached response.
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    /// Rate limiter that paces the API calls of this instance, if any.
+    pub fn rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+        self.rate_limiter.clone()
+    }
+
+    /// OAuth scopes granted to the access token, as reported
+    /// by the most recent API response that included them.
+    pub fn granted_scopes(&self) -> Option<Vec<String>> {
+        self.granted_scopes.lock().unwrap().clone()
+    }
+
+    // Make the API call to `slack_method` described by `request`,
+    // reporting any warning and mapping any error in its response.
+    fn invoke<T>(&self,
+                 slack_method: &str,
+                 accepted_scopes: &[&str],
+                 retry_policy: Option<RetryPolicy>,
+                 idempotent: bool,
+                 arguments: &[(&str, &str)],
+                 request: &HttpRequest)
+                 -> Result<T>
+        where T: Deserialize + Serialize + ApiResponse
+    {
+        let started = Instant::now();
+        let mut stats = CallStats::default();
+        let result = self.perform::<T>(slack_method,
+                                       accepted_scopes,
+                                       retry_policy,
+                                       idempotent,
+                                       arguments,
+                                       request,
+                                       &mut stats);
+        if let Some(ref observer) = self.metrics_observer {
+            observer.observe(&CallMetrics {
+                method: slack_method,
+                duration: started.elapsed(),
+                status: stats.status,
+                error: result.as_ref().err().map(|e| error_kind_name(e.kind()).to_string()),
+                retries: stats.retries,
+            });
+        }
+        result
+    }
+
+    // `invoke`, recording the last HTTP status and the number of retries in `stats`.
+    fn perform<T>(&self,
+                  slack_method: &str,
+                  accepted_scopes: &[&str],
+                  retry_policy: Option<RetryPolicy>,
+                  idempotent: bool,
+                  arguments: &[(&str, &str)],
+                  request: &HttpRequest,
+                  stats: &mut CallStats)
+                  -> Result<T>
+        where T: Deserialize + Serialize + ApiResponse
+    {
+        try!(self.preflight_scopes(slack_method, accepted_scopes));
+
+        let channel = arguments.iter().find(|&&(name, _)| name == "channel").map(|&(_, value)| value);
+        let deserialized = try!(self.attempt::<T>(slack_method, channel, retry_policy, idempotent, request, stats));
+        if let Some(warning) = deserialized.warning() {
+            (self.warning_handler)(slack_method, warning);
+        }
+        match deserialized.error() {
+            _ if deserialized.ok() => Ok(deserialized),
+            Some("missing_scope") => {
+                let needed = deserialized.needed_scopes().map_or_else(Vec::new, split_scopes);
+                let provided = deserialized.provided_scopes().map_or_else(Vec::new, split_scopes);
+                Err(ErrorKind::MissingScope(needed, provided).into())
+            }
+            error => Err(from_api_error_string(&error.unwrap_or("unknown_error").to_string())),
+        }
     }
 
-    pub fn send<T>(request: RequestBuilder) -> Result<T> where T: Deserialize {
-        request.send().map_err(From::from).and_then(|mut r| deserialize::<T>(&mut r))
+    // Make the API call, retrying as allowed by `retry_policy` (if any)
+    // if it fails because of a transient error.
+    fn attempt<T>(&self,
+                  slack_method: &str,
+                  channel: Option<&str>,
+                  retry_policy: Option<RetryPolicy>,
+                  idempotent: bool,
+                  request: &HttpRequest,
+                  stats: &mut CallStats)
+                  -> Result<T>
+        where T: Deserialize + Serialize + ApiResponse
+    {
+        let mut retries = 0;
+        loop {
+            let result = self.authenticated::<T>(slack_method, channel, request, stats);
+            let reason = match result {
+                Ok(ref deserialized) if !deserialized.ok() => {
+                    deserialized.error().and_then(|error| {
+                        if retry::is_transient_api_error(error) {
+                            Some(format!("api error {}", error))
+                        } else {
+                            None
+                        }
+                    })
+                }
+                Ok(_) => None,
+                Err(ref e) => retry::transient_error(e),
+            };
+            let wait = retry_policy.and_then(|p| p.wait_before_retry(retries, idempotent));
+            match (reason, wait) {
+                (Some(reason), Some(wait)) => {
+                    retries += 1;
+                    stats.retries += 1;
+                    warn!("slack api call {}: {}; retry {} after {:?}", slack_method, reason, retries, wait);
+                    thread::sleep(wait);
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    // Make the API call with the provider's access token, refreshing
+    // the token and calling again once if Slack reports it expired.
+    fn authenticated<T>(&self,
+                        slack_method: &str,
+                        channel: Option<&str>,
+                        request: &HttpRequest,
+                        stats: &mut CallStats)
+                        -> Result<T>
+        where T: Deserialize + Serialize + ApiResponse
+    {
+        let access_token = try!(self.token_provider.access_token());
+        let mut response = try!(self.execute(slack_method, channel, &access_token, request, stats));
+        let deserialized = try!(self.parse::<T>(slack_method, &mut response));
+        if deserialized.ok() || deserialized.error() != Some("token_expired") {
+            return Ok(deserialized);
+        }
+        debug!("slack api call {}: access token {} expired; refreshing", slack_method, access_token);
+        let access_token = try!(self.token_provider.refresh(&access_token));
+        stats.retries += 1;
+        let mut response = try!(self.execute(slack_method, channel, &access_token, request, stats));
+        self.parse::<T>(slack_method, &mut response)
+    }
+
+    // Deserialize the response to `slack_method`, reporting any
+    // fields `T` doesn't have to the unknown-field handler (if any).
+    fn parse<T>(&self, slack_method: &str, response: &mut HttpResponse) -> Result<T>
+        where T: Deserialize + Serialize
+    {
+        let handler = match self.unknown_field_handler {
+            Some(ref handler) => handler,
+            None => return deserialize(response),
+        };
+        let received: serde_json::Value = try!(deserialize(response));
+        let deserialized: T = try!(serde_json::from_value(received.clone()));
+        for field in drift::unknown_fields(&received, &serde_json::to_value(&deserialized)) {
+            handler(slack_method, &field);
+        }
+        Ok(deserialized)
+    }
+
+    // Timestamp of the recent message in `channel` tagged with `key`
+    // by `chat_post_message_idempotent`, if there is one.
+    fn find_tagged_message(&self, channel: &ChannelId, key: &str) -> Result<Option<Timestamp>> {
+        let response = try!(self.call::<ConversationsHistory>(&(channel.clone(), IDEMPOTENCY_HISTORY_LIMIT)));
+        let tagged = response.messages.unwrap_or_default().into_iter().find(|m| {
+            m.metadata.as_ref().and_then(|metadata| metadata.event_payload.get(IDEMPOTENCY_KEY_FIELD)) ==
+            Some(&serde_json::Value::String(key.to_string()))
+        });
+        Ok(tagged.map(|m| m.ts))
+    }
+
+    // Fail if the scopes granted to the access token are known and don't
+    // include any of the scopes that allow `slack_method` to be called.
+    // If `accepted_scopes` is empty, the scopes reported for `slack_method`
+    // by an earlier `X-Accepted-OAuth-Scopes` header are used instead.
+    fn preflight_scopes(&self, slack_method: &str, accepted_scopes: &[&str]) -> Result<()> {
+        if !self.scope_preflight {
+            return Ok(());
+        }
+        let granted = match *self.granted_scopes.lock().unwrap() {
+            Some(ref granted) => granted.clone(),
+            None => return Ok(()),
+        };
+        let accepted: Vec<String> = if accepted_scopes.is_empty() {
+            self.accepted_scopes.lock().unwrap().get(slack_method).cloned().unwrap_or_default()
+        } else {
+            accepted_scopes.iter().map(|s| s.to_string()).collect()
+        };
+
+        if accepted.is_empty() || accepted.iter().any(|s| granted.contains(s)) {
+            Ok(())
+        } else {
+            Err(ErrorKind::MissingScope(accepted, granted).into())
+        }
+    }
+
+    // Remember the scopes reported by the OAuth headers of a response to `slack_method`.
+    fn record_scopes(&self, slack_method: &str, headers: &Headers) {
+        if let Some(granted) = scope_header(headers, OAUTH_SCOPES_HEADER) {
+            *self.granted_scopes.lock().unwrap() = Some(granted);
+        }
+        if let Some(accepted) = scope_header(headers, ACCEPTED_OAUTH_SCOPES_HEADER) {
+            self.accepted_scopes.lock().unwrap().insert(slack_method.to_string(), accepted);
+        }
+    }
+
+    // Encoding used for the arguments of `slack_method`. The Slack Web
+    // API accepts form-encoded POST bodies for every method, and these
+    // don't run into the URL length limits that query strings do.
+    // JSON bodies are only accepted by write methods, and must be
+    // accompanied by an `Authorization` header.
+    fn request_encoding(&self, slack_method: &str) -> RequestEncoding {
+        if self.json_requests && self.token_placement == TokenPlacement::AuthorizationHeader &&
+           accepts_json_body(slack_method) {
+            RequestEncoding::Json
+        } else {
+            RequestEncoding::Form
+        }
+    }
+
+    // Send `request`, waiting for the rate limiter (if any) beforehand and
+    // retrying as allowed by the rate-limit policy (if any) afterwards.
+    fn execute(&self,
+               slack_method: &str,
+               channel: Option<&str>,
+               access_token: &AccessToken,
+               request: &HttpRequest,
+               stats: &mut CallStats)
+               -> Result<HttpResponse> {
+        let request = self.prepare(request, access_token);
+        let mut retries = 0;
+        loop {
+            self.throttle(slack_method, channel);
+
+            debug!("slack api call {}: {}", slack_method, request.redacted_arguments());
+            let started = Instant::now();
+            let response = try!(self.transport.send(&request));
+            let latency = started.elapsed();
+            stats.status = Some(response.status);
+            self.record_scopes(slack_method, &response.headers);
+            debug!("slack api call {}: status {} in {}ms (x-slack-req-id: {})",
+                   slack_method,
+                   response.status,
+                   latency.as_secs() * 1000 + (latency.subsec_nanos() / 1_000_000) as u64,
+                   response.request_id().unwrap_or("none"));
+
+            if response.status != StatusCode::TooManyRequests {
+                return Ok(response);
+            }
+
+            let retry_after = retry::retry_after(&response.headers);
+            let wait = self.rate_limit_policy.and_then(|p| p.wait_before_retry(retries, retry_after));
+            match wait {
+                Some(wait) => {
+                    retries += 1;
+                    stats.retries += 1;
+                    debug!("slack api call {}: rate-limited; retry {} after {:?}", slack_method, retries, wait);
+                    thread::sleep(wait);
+                }
+                None => return Err(ErrorKind::RateLimited(retry_after).into()),
+            }
+        }
+    }
+
+    // Return a copy of `request` that carries
+    // `access_token` and the `User-Agent` header.
+    fn prepare(&self, request: &HttpRequest, access_token: &AccessToken) -> HttpRequest {
+        let mut request = request.clone();
+        request.headers.set(UserAgent(self.user_agent.clone()));
+        match self.token_placement {
+            TokenPlacement::AuthorizationHeader => {
+                request.headers.set(Authorization(Bearer { token: access_token.secret().to_string() }));
+            }
+            TokenPlacement::QueryParameter => {
+                request.url.query_pairs_mut().append_pair("token", access_token.secret());
+            }
+        }
+        request
+    }
+
+    // Block until the rate limiter (if any) allows a call to `slack_method`.
+    fn throttle(&self, slack_method: &str, channel: Option<&str>) {
+        if let Some(ref limiter) = self.rate_limiter {
+            limiter.acquire(slack_method, channel);
+        }
     }
 }
 
-fn deserialize<T>(response: &mut Response) -> Result<T> where T: Deserialize {
-    let content_length = response.headers
-        .get::<ContentLength>()
-        .map_or(DEFAULT_RESPONSE_CONTENT_LENGTH, |c| { let ContentLength(length) = *c; length as usize });
-    let mut body = String::with_capacity(content_length);
-    try!(response.read_to_string(&mut body));
-    serde_json::from_str::<T>(&body).map_err(From::from)
+fn scope_header(headers: &Headers, name: &str) -> Option<Vec<String>> {
+    headers.get_raw(name)
+        .and_then(|values| values.first())
+        .and_then(|value| str::from_utf8(value).ok())
+        .map(split_scopes)
 }
 
-fn validate_channel_id(channel: &ChannelId) -> Result<()> {
-    match channel.chars().next() {
-        Some('C') | Some('D') | Some('G') => Ok(()),
-        _ => Err(ErrorKind::InvalidChannelId.into())
-    }
+fn split_scopes(scopes: &str) -> Vec<String> {
+    scopes.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_string()).collect()
+}
+
+fn log_warning(slack_method: &str, warning: &str) {
+    warn!("slack api call {}: warning: {}", slack_method, warning);
+}
+
+fn deserialize<T>(response: &mut HttpResponse) -> Result<T> where T: Deserialize {
+    serde_json::from_reader(BufReader::new(&mut response.body)).map_err(From::from)
 }
 
-fn validate_user_id(user: &UserId) -> Result<()> {
-    match user.chars().next() {
-        Some('U') => Ok(()),
-        _ => Err(ErrorKind::InvalidUserId.into())
+fn accepts_json_body(slack_method: &str) -> bool {
+    match slack_method {
+        "channels.archive" | "channels.create" | "channels.invite" | "channels.join" | "channels.kick" |
+        "channels.leave" | "channels.mark" | "channels.rename" | "channels.setPurpose" | "channels.setTopic" |
+        "channels.unarchive" | "chat.delete" | "chat.meMessage" | "chat.postMessage" | "chat.update" => true,
+        _ => false,
     }
 }
@@ -0,0 +1,883 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{Cursor, Read};
+use std::net::ToSocketAddrs;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{Authorization, Bearer, ContentType, Headers};
+use hyper::mime::{Mime, SubLevel, TopLevel};
+use hyper::server::{Handler, Listening, Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use hyper::Url;
+use serde_json::{self, Map, Value};
+use url::form_urlencoded;
+
+use base_types::{ChannelId, Timestamp, UserId};
+use errors::*;
+use transport::{HttpRequest, HttpResponse, Transport};
+
+static GENERAL_CHANNEL_NAME: &'static str = "general";
+static OWNER_USER_NAME: &'static str = "owner";
+const MAX_CHANNEL_NAME_LENGTH: usize = 21;
+const MAX_PURPOSE_OR_TOPIC_LENGTH: usize = 250;
+const MAX_MESSAGE_LENGTH: usize = 40000;
+const MAX_ATTACHMENTS: usize = 100;
+
+/// In-memory stand-in for a Slack workspace that speaks the Web API.
+///
+/// A `FakeSlack` holds users, channels and the messages posted
+/// to them, and answers `api.test`, `channels.*` and `chat.*`
+/// calls with the same JSON that Slack returns, including the
+/// error strings Slack uses when a call fails (`name_taken`,
+/// `is_archived`, `cant_archive_general`, etc.).
+///
+/// It can be used directly as the `Transport` of a `Slack`
+/// instance, or served over HTTP on a local port with `serve`.
+/// Clones share the same workspace.
+///
+/// Only available with the `test-server` feature.
+#[derive(Clone)]
+pub struct FakeSlack {
+    workspace: Arc<Mutex<Workspace>>,
+}
+
+/// Message posted to a channel in a `FakeSlack` workspace.
+#[derive(Clone, Debug)]
+pub struct FakeMessage {
+    /// Message timestamp, as returned by Slack.
+    pub ts: String,
+    /// Id of the user who posted the message.
+    pub user: UserId,
+    /// Message text, if any.
+    pub text: Option<String>,
+    /// Message attachments, if any.
+    pub attachments: Option<Value>,
+    /// `me_message` for messages posted with `chat.meMessage`.
+    pub subtype: Option<String>,
+}
+
+struct Workspace {
+    next_id: u32,
+    next_ts: u32,
+    users: Vec<FakeUser>,
+    channels: Vec<FakeChannel>,
+}
+
+struct FakeUser {
+    id: UserId,
+    name: String,
+    token: String,
+}
+
+struct FakeChannel {
+    id: ChannelId,
+    name: String,
+    created: u64,
+    creator: UserId,
+    is_archived: bool,
+    is_general: bool,
+    members: Vec<UserId>,
+    // value, id of the user who set it (empty if never set) and time set
+    topic: (String, String, u64),
+    purpose: (String, String, u64),
+    last_read: Option<String>,
+    messages: Vec<FakeMessage>,
+}
+
+// Error string with which a failed call is answered.
+type CallResult = ::std::result::Result<Map<String, Value>, &'static str>;
+
+impl FakeSlack {
+    /// Create a workspace with a `#general` channel and
+    /// a single user that authenticates with `token`.
+    pub fn new(token: &str) -> FakeSlack {
+        let mut workspace = Workspace {
+            next_id: 1,
+            next_ts: 1,
+            users: Vec::new(),
+            channels: Vec::new(),
+        };
+        let owner = workspace.add_user(OWNER_USER_NAME, token);
+        workspace.add_channel(GENERAL_CHANNEL_NAME, &owner);
+        workspace.channels[0].is_general = true;
+
+        FakeSlack { workspace: Arc::new(Mutex::new(workspace)) }
+    }
+
+    /// Add a user named `name` that authenticates with `token`.
+    /// The user is made a member of `#general`.
+    pub fn add_user(&self, name: &str, token: &str) -> UserId {
+        let mut workspace = self.workspace.lock().unwrap();
+        let user = workspace.add_user(name, token);
+        workspace.channels[0].members.push(user.clone());
+        user
+    }
+
+    /// Add a channel named `name`, created by the workspace's first user.
+    pub fn add_channel(&self, name: &str) -> ChannelId {
+        let mut workspace = self.workspace.lock().unwrap();
+        let owner = workspace.users[0].id.clone();
+        workspace.add_channel(name, &owner)
+    }
+
+    /// Id of the user named `name`, if any.
+    pub fn user_id(&self, name: &str) -> Option<UserId> {
+        let workspace = self.workspace.lock().unwrap();
+        workspace.users.iter().find(|u| u.name == name).map(|u| u.id.clone())
+    }
+
+    /// Id of the channel named `name`, if any.
+    pub fn channel_id(&self, name: &str) -> Option<ChannelId> {
+        let workspace = self.workspace.lock().unwrap();
+        workspace.channels.iter().find(|c| c.name == name).map(|c| c.id.clone())
+    }
+
+    /// Messages posted to `channel`, oldest first.
+    pub fn messages(&self, channel: &ChannelId) -> Vec<FakeMessage> {
+        let workspace = self.workspace.lock().unwrap();
+        workspace.channels.iter().find(|c| &c.id == channel).map_or(Vec::new(), |c| c.messages.clone())
+    }
+
+    /// Serve the workspace over HTTP on `address`. Use port `0`
+    /// to have the operating system pick an unused port, and
+    /// `FakeSlackServer::base_url` to find out which it was.
+    pub fn serve<A>(&self, address: A) -> Result<FakeSlackServer> where A: ToSocketAddrs {
+        let server = try!(Server::http(address));
+        let listening = try!(server.handle(FakeSlackHandler { slack: self.clone() }));
+        let base_url = format!("http://{}/api", listening.socket);
+        Ok(FakeSlackServer {
+            listening: listening,
+            base_url: base_url,
+        })
+    }
+
+    // Answer the call described by `request` with a JSON object.
+    fn answer(&self, request: &HttpRequest) -> Value {
+        let slack_method = request.url.path_segments().and_then(|s| s.last()).unwrap_or("").to_string();
+        let arguments = request_arguments(request);
+        let token = match request.headers.get::<Authorization<Bearer>>() {
+            Some(&Authorization(Bearer { ref token })) => Some(token.clone()),
+            None => argument(&arguments, "token").map(|t| t.to_string()),
+        };
+
+        let mut workspace = self.workspace.lock().unwrap();
+        let result = if slack_method == "api.test" {
+            workspace.api_test(&arguments)
+        } else {
+            match token.as_ref().map(|t| workspace.authenticate(t)) {
+                None => Err("not_authed"),
+                Some(None) => Err("invalid_auth"),
+                Some(Some(caller)) => workspace.dispatch(&slack_method, &caller, &arguments),
+            }
+        };
+
+        let mut object = match result {
+            Ok(object) => object,
+            Err(error) => object_with("error", Value::String(error.to_string())),
+        };
+        let ok = !object.contains_key("error");
+        object.insert("ok".to_string(), Value::Bool(ok));
+        Value::Object(object)
+    }
+}
+
+impl Transport for FakeSlack {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let body = serde_json::to_string(&self.answer(request)).expect("response serializes to json");
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        Ok(HttpResponse {
+            status: StatusCode::Ok,
+            headers: headers,
+            body: Box::new(Cursor::new(body.into_bytes())),
+        })
+    }
+}
+
+/// `FakeSlack` workspace served over HTTP. Created by `FakeSlack::serve`.
+///
+/// Because of a limitation of the HTTP server, the listening socket
+/// stays open until the process exits, even after this is dropped.
+pub struct FakeSlackServer {
+    listening: Listening,
+    base_url: String,
+}
+
+impl FakeSlackServer {
+    /// Base URL of the server's Web API, suitable for `SlackBuilder::base_url`.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+impl Drop for FakeSlackServer {
+    fn drop(&mut self) {
+        // detach the server thread instead of waiting for it to finish
+        let _ = self.listening.close();
+    }
+}
+
+struct FakeSlackHandler {
+    slack: FakeSlack,
+}
+
+impl Handler for FakeSlackHandler {
+    fn handle(&self, mut req: Request, mut res: Response) {
+        let path = match req.uri {
+            RequestUri::AbsolutePath(ref path) => path.clone(),
+            RequestUri::AbsoluteUri(ref url) => url.path().to_string(),
+            _ => String::new(),
+        };
+        let url = match Url::parse(&format!("http://localhost{}", path)) {
+            Ok(url) => url,
+            Err(_) => {
+                *res.status_mut() = StatusCode::BadRequest;
+                let _ = res.send(b"");
+                return;
+            }
+        };
+
+        let mut body = Vec::new();
+        let _ = req.read_to_end(&mut body);
+        let request = HttpRequest {
+            method: req.method.clone(),
+            url: url,
+            headers: req.headers.clone(),
+            body: if body.is_empty() { None } else { Some(body) },
+        };
+
+        let answer = serde_json::to_string(&self.slack.answer(&request)).expect("response serializes to json");
+        res.headers_mut().set(ContentType::json());
+        let _ = res.send(answer.as_bytes());
+    }
+}
+
+impl Workspace {
+    fn add_user(&mut self, name: &str, token: &str) -> UserId {
+        let id: UserId = self.next_id("U").parse().expect("generated user ids are valid");
+        self.users.push(FakeUser {
+            id: id.clone(),
+            name: name.to_string(),
+            token: token.to_string(),
+        });
+        id
+    }
+
+    fn add_channel(&mut self, name: &str, creator: &UserId) -> ChannelId {
+        let id: ChannelId = self.next_id("C").parse().expect("generated channel ids are valid");
+        let now = now();
+        self.channels.push(FakeChannel {
+            id: id.clone(),
+            name: name.to_string(),
+            created: now,
+            creator: creator.clone(),
+            is_archived: false,
+            is_general: false,
+            members: vec![creator.clone()],
+            topic: (String::new(), String::new(), 0),
+            purpose: (String::new(), String::new(), 0),
+            last_read: None,
+            messages: Vec::new(),
+        });
+        id
+    }
+
+    fn next_id(&mut self, prefix: &str) -> String {
+        let id = format!("{}{:08X}", prefix, self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn next_ts(&mut self) -> String {
+        let ts = format!("{}.{:06}", now(), self.next_ts);
+        self.next_ts += 1;
+        ts
+    }
+
+    fn authenticate(&self, token: &str) -> Option<UserId> {
+        self.users.iter().find(|u| u.token == token).map(|u| u.id.clone())
+    }
+
+    fn dispatch(&mut self, slack_method: &str, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        match slack_method {
+            "channels.archive" => self.channels_archive(caller, arguments),
+            "channels.create" => self.channels_create(caller, arguments),
+            "channels.info" => self.channels_info(caller, arguments),
+            "channels.invite" => self.channels_invite(caller, arguments),
+            "channels.join" => self.channels_join(caller, arguments),
+            "channels.kick" => self.channels_kick(caller, arguments),
+            "channels.leave" => self.channels_leave(caller, arguments),
+            "channels.list" => self.channels_list(caller, arguments),
+            "channels.mark" => self.channels_mark(caller, arguments),
+            "channels.rename" => self.channels_rename(caller, arguments),
+            "channels.setPurpose" => self.channels_set_purpose_or_topic(caller, arguments, "purpose"),
+            "channels.setTopic" => self.channels_set_purpose_or_topic(caller, arguments, "topic"),
+            "channels.unarchive" => self.channels_unarchive(caller, arguments),
+            "chat.delete" => self.chat_delete(caller, arguments),
+            "chat.meMessage" => self.chat_me_message(caller, arguments),
+            "chat.postMessage" => self.chat_post_message(caller, arguments),
+            "chat.update" => self.chat_update(caller, arguments),
+            _ => Err("unknown_method"),
+        }
+    }
+
+    //
+    // api
+    //
+
+    fn api_test(&mut self, arguments: &[(String, String)]) -> CallResult {
+        if let Some(error) = argument(arguments, "error") {
+            // the error string is echoed back, so it can't be a &'static str
+            let mut object = Map::new();
+            object.insert("error".to_string(), Value::String(error.to_string()));
+            object.insert("args".to_string(), echoed_arguments(arguments));
+            return Ok(object);
+        }
+        let mut object = Map::new();
+        object.insert("args".to_string(), echoed_arguments(arguments));
+        Ok(object)
+    }
+
+    //
+    // channels
+    //
+
+    fn channels_archive(&mut self, _caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let channel = try!(self.channel_mut(arguments));
+        if channel.is_general {
+            return Err("cant_archive_general");
+        }
+        if channel.is_archived {
+            return Err("already_archived");
+        }
+        channel.is_archived = true;
+        Ok(Map::new())
+    }
+
+    fn channels_create(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let name = try!(self.valid_channel_name(arguments, None));
+        let id = self.add_channel(&name, caller);
+        let channel = self.channels.iter().find(|c| c.id == id).expect("created channel exists");
+        Ok(object_with("channel", channel.to_json(caller)))
+    }
+
+    fn channels_info(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let channel = try!(self.channel_mut(arguments));
+        Ok(object_with("channel", channel.to_json(caller)))
+    }
+
+    fn channels_invite(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let user = try!(self.user(arguments));
+        let channel = try!(self.channel_mut(arguments));
+        if &user == caller {
+            return Err("cant_invite_self");
+        }
+        if channel.is_archived {
+            return Err("is_archived");
+        }
+        if !channel.members.contains(caller) {
+            return Err("not_in_channel");
+        }
+        if channel.members.contains(&user) {
+            return Err("already_in_channel");
+        }
+        channel.members.push(user);
+        Ok(object_with("channel", channel.to_json(caller)))
+    }
+
+    fn channels_join(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let name = argument(arguments, "name").unwrap_or("").trim_left_matches('#').to_string();
+        if !self.channels.iter().any(|c| c.name == name) {
+            let mut object = try!(self.channels_create(caller, &[("name".to_string(), name)]));
+            object.insert("already_in_channel".to_string(), Value::Bool(false));
+            return Ok(object);
+        }
+
+        let channel = self.channels.iter_mut().find(|c| c.name == name).expect("channel exists");
+        if channel.is_archived {
+            return Err("is_archived");
+        }
+        let already_in_channel = channel.members.contains(caller);
+        if !already_in_channel {
+            channel.members.push(caller.clone());
+        }
+        let mut object = object_with("channel", channel.to_json(caller));
+        object.insert("already_in_channel".to_string(), Value::Bool(already_in_channel));
+        Ok(object)
+    }
+
+    fn channels_kick(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let user = try!(self.user(arguments));
+        let channel = try!(self.channel_mut(arguments));
+        if &user == caller {
+            return Err("cant_kick_self");
+        }
+        if channel.is_general {
+            return Err("cant_kick_from_general");
+        }
+        if channel.is_archived {
+            return Err("is_archived");
+        }
+        if !channel.members.contains(&user) {
+            return Err("not_in_channel");
+        }
+        channel.members.retain(|m| m != &user);
+        Ok(Map::new())
+    }
+
+    fn channels_leave(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let channel = try!(self.channel_mut(arguments));
+        if channel.is_general {
+            return Err("cant_leave_general");
+        }
+        if channel.is_archived {
+            return Err("is_archived");
+        }
+        if !channel.members.contains(caller) {
+            return Ok(object_with("not_in_channel", Value::Bool(true)));
+        }
+        channel.members.retain(|m| m != caller);
+        Ok(Map::new())
+    }
+
+    fn channels_list(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let exclude_archived = is_true(argument(arguments, "exclude_archived"));
+        let channels = self.channels
+            .iter()
+            .filter(|c| !(exclude_archived && c.is_archived))
+            .map(|c| c.to_json(caller))
+            .collect();
+        Ok(object_with("channels", Value::Array(channels)))
+    }
+
+    fn channels_mark(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let ts = try!(timestamp(arguments));
+        let channel = try!(self.channel_mut(arguments));
+        if !channel.members.contains(caller) {
+            return Err("not_in_channel");
+        }
+        channel.last_read = Some(ts);
+        Ok(Map::new())
+    }
+
+    fn channels_rename(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let id = try!(self.channel_mut(arguments)).id.clone();
+        let name = try!(self.valid_channel_name(arguments, Some(&id)));
+        let channel = try!(self.channel_mut(arguments));
+        if channel.is_archived {
+            return Err("is_archived");
+        }
+        if !channel.members.contains(caller) {
+            return Err("not_in_channel");
+        }
+        channel.name = name;
+
+        let mut renamed = Map::new();
+        renamed.insert("id".to_string(), Value::String(channel.id.to_string()));
+        renamed.insert("is_channel".to_string(), Value::Bool(true));
+        renamed.insert("name".to_string(), Value::String(channel.name.clone()));
+        renamed.insert("created".to_string(), Value::U64(channel.created));
+        Ok(object_with("channel", Value::Object(renamed)))
+    }
+
+    fn channels_set_purpose_or_topic(&mut self,
+                                     caller: &UserId,
+                                     arguments: &[(String, String)],
+                                     field: &'static str)
+                                     -> CallResult {
+        let value = argument(arguments, field).unwrap_or("").to_string();
+        let channel = try!(self.channel_mut(arguments));
+        if channel.is_archived {
+            return Err("is_archived");
+        }
+        if !channel.members.contains(caller) {
+            return Err("not_in_channel");
+        }
+        if value.chars().count() > MAX_PURPOSE_OR_TOPIC_LENGTH {
+            return Err("too_long");
+        }
+
+        let set = (value.clone(), caller.to_string(), now());
+        if field == "purpose" {
+            channel.purpose = set;
+        } else {
+            channel.topic = set;
+        }
+        Ok(object_with(field, Value::String(value)))
+    }
+
+    fn channels_unarchive(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let channel = try!(self.channel_mut(arguments));
+        if !channel.is_archived {
+            return Err("not_archived");
+        }
+        channel.is_archived = false;
+        if !channel.members.contains(caller) {
+            channel.members.push(caller.clone());
+        }
+        Ok(Map::new())
+    }
+
+    //
+    // chat
+    //
+
+    fn chat_delete(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let ts = try!(timestamp(arguments));
+        let channel = try!(self.channel_mut(arguments));
+        let index = try!(channel.messages.iter().position(|m| m.ts == ts).ok_or("message_not_found"));
+        if &channel.messages[index].user != caller {
+            return Err("cant_delete_message");
+        }
+        let message = channel.messages.remove(index);
+
+        let mut object = object_with("channel", Value::String(channel.id.to_string()));
+        object.insert("ts".to_string(), ts_to_json(&message.ts));
+        Ok(object)
+    }
+
+    fn chat_me_message(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let text = try!(argument(arguments, "text").ok_or("no_text")).to_string();
+        self.post(caller, arguments, Some(text), None, Some("me_message".to_string()))
+    }
+
+    fn chat_post_message(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let text = argument(arguments, "text").map(|t| t.to_string());
+        let attachments = match argument(arguments, "attachments") {
+            Some(attachments) => {
+                let parsed: Value = try!(serde_json::from_str(attachments).map_err(|_| "invalid_attachments"));
+                if parsed.as_array().map_or(0, |a| a.len()) > MAX_ATTACHMENTS {
+                    return Err("too_many_attachments");
+                }
+                Some(parsed)
+            }
+            None => None,
+        };
+        self.post(caller, arguments, text, attachments, None)
+    }
+
+    fn chat_update(&mut self, caller: &UserId, arguments: &[(String, String)]) -> CallResult {
+        let ts = try!(timestamp(arguments));
+        let text = argument(arguments, "text").map(|t| t.to_string());
+        let channel = try!(self.channel_mut(arguments));
+        let message = try!(channel.messages.iter_mut().find(|m| m.ts == ts).ok_or("message_not_found"));
+        if &message.user != caller {
+            return Err("cant_update_message");
+        }
+        if text.as_ref().map_or(true, |t| t.is_empty()) {
+            return Err("no_text");
+        }
+        message.text = text;
+
+        let mut object = object_with("channel", Value::String(channel.id.to_string()));
+        object.insert("ts".to_string(), ts_to_json(&message.ts));
+        object.insert("text".to_string(), Value::String(message.text.clone().unwrap_or_default()));
+        Ok(object)
+    }
+
+    // Post a message to the channel named by the `channel` argument
+    // (either a channel id, or a channel name with or without a `#`).
+    fn post(&mut self,
+            caller: &UserId,
+            arguments: &[(String, String)],
+            text: Option<String>,
+            attachments: Option<Value>,
+            subtype: Option<String>)
+            -> CallResult {
+        if text.as_ref().map_or(true, |t| t.is_empty()) && attachments.is_none() {
+            return Err("no_text");
+        }
+        if text.as_ref().map_or(0, |t| t.chars().count()) > MAX_MESSAGE_LENGTH {
+            return Err("msg_too_long");
+        }
+
+        let ts = self.next_ts();
+        let channel = try!(self.channel_mut(arguments));
+        if channel.is_archived {
+            return Err("is_archived");
+        }
+        if !channel.members.contains(caller) {
+            return Err("not_in_channel");
+        }
+        let message = FakeMessage {
+            ts: ts.clone(),
+            user: caller.clone(),
+            text: text,
+            attachments: attachments,
+            subtype: subtype,
+        };
+
+        let mut posted = Map::new();
+        posted.insert("type".to_string(), Value::String("message".to_string()));
+        posted.insert("user".to_string(), Value::String(message.user.to_string()));
+        posted.insert("ts".to_string(), ts_to_json(&ts));
+        if let Some(ref text) = message.text {
+            posted.insert("text".to_string(), Value::String(text.clone()));
+        }
+        if let Some(ref attachments) = message.attachments {
+            posted.insert("attachments".to_string(), attachments.clone());
+        }
+        channel.messages.push(message);
+
+        let mut object = object_with("channel", Value::String(channel.id.to_string()));
+        object.insert("ts".to_string(), ts_to_json(&ts));
+        object.insert("message".to_string(), Value::Object(posted));
+        Ok(object)
+    }
+
+    //
+    // lookups
+    //
+
+    fn channel_mut(&mut self, arguments: &[(String, String)]) -> ::std::result::Result<&mut FakeChannel, &'static str> {
+        let channel = try!(argument(arguments, "channel").ok_or("channel_not_found"));
+        let name = channel.trim_left_matches('#');
+        self.channels
+            .iter_mut()
+            .find(|c| c.id.as_str() == channel || c.name == name)
+            .ok_or("channel_not_found")
+    }
+
+    fn user(&self, arguments: &[(String, String)]) -> ::std::result::Result<UserId, &'static str> {
+        let user = try!(argument(arguments, "user").ok_or("user_not_found"));
+        self.users.iter().find(|u| u.id.as_str() == user).map(|u| u.id.clone()).ok_or("user_not_found")
+    }
+
+    // The `name` argument, if it's a valid name that isn't used
+    // by any channel other than `renamed` (the channel being renamed).
+    fn valid_channel_name(&self,
+                          arguments: &[(String, String)],
+                          renamed: Option<&ChannelId>)
+                          -> ::std::result::Result<String, &'static str> {
+        let name = argument(arguments, "name").unwrap_or("");
+        if name.is_empty() {
+            return Err("no_channel");
+        }
+        if name.chars().count() > MAX_CHANNEL_NAME_LENGTH {
+            return Err("invalid_name_maxlength");
+        }
+        if !name.chars().all(|c| c.is_lowercase() || c.is_numeric() || c == '-' || c == '_') {
+            return Err("invalid_name_specials");
+        }
+        if self.channels.iter().any(|c| c.name == name && Some(&c.id) != renamed) {
+            return Err("name_taken");
+        }
+        Ok(name.to_string())
+    }
+}
+
+impl FakeChannel {
+    // JSON for this channel as seen by `caller`.
+    fn to_json(&self, caller: &UserId) -> Value {
+        let mut channel = Map::new();
+        channel.insert("id".to_string(), Value::String(self.id.to_string()));
+        channel.insert("name".to_string(), Value::String(self.name.clone()));
+        channel.insert("created".to_string(), Value::U64(self.created));
+        channel.insert("creator".to_string(), Value::String(self.creator.to_string()));
+        channel.insert("is_archived".to_string(), Value::Bool(self.is_archived));
+        channel.insert("is_general".to_string(), Value::Bool(self.is_general));
+        channel.insert("members".to_string(),
+                       Value::Array(self.members.iter().map(|m| Value::String(m.to_string())).collect()));
+        channel.insert("topic".to_string(), set_by_json(&self.topic));
+        channel.insert("purpose".to_string(), set_by_json(&self.purpose));
+        channel.insert("is_member".to_string(), Value::Bool(self.members.contains(caller)));
+        if let Some(ref last_read) = self.last_read {
+            channel.insert("last_read".to_string(), ts_to_json(last_read));
+        }
+        Value::Object(channel)
+    }
+}
+
+// Request arguments from the query string and the form-encoded or JSON body.
+fn request_arguments(request: &HttpRequest) -> Vec<(String, String)> {
+    let mut arguments: Vec<(String, String)> = request.url.query_pairs().into_owned().collect();
+    if let Some(ref body) = request.body {
+        match request.headers.get::<ContentType>() {
+            Some(&ContentType(Mime(TopLevel::Application, SubLevel::Json, _))) => {
+                let object: Map<String, Value> = serde_json::from_slice(body).unwrap_or_default();
+                arguments.extend(object.into_iter().map(|(name, value)| {
+                    let value = match value {
+                        Value::String(s) => s,
+                        v => v.to_string(),
+                    };
+                    (name, value)
+                }));
+            }
+            _ => arguments.extend(form_urlencoded::parse(body).into_owned()),
+        }
+    }
+    arguments
+}
+
+fn argument<'a>(arguments: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    arguments.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref v)| v.as_str())
+}
+
+// Arguments echoed back by `api.test`, which never include the token.
+fn echoed_arguments(arguments: &[(String, String)]) -> Value {
+    Value::Object(arguments.iter()
+        .filter(|&&(ref name, _)| name != "token")
+        .map(|&(ref name, ref value)| (name.clone(), Value::String(value.clone())))
+        .collect())
+}
+
+// Slack matches timestamps exactly, so `1.000100` and `1.0001` differ.
+fn timestamp(arguments: &[(String, String)]) -> ::std::result::Result<String, &'static str> {
+    match argument(arguments, "ts") {
+        Some(ts) if ts.parse::<Timestamp>().is_ok() => Ok(ts.to_string()),
+        _ => Err("invalid_timestamp"),
+    }
+}
+
+// Slack sends timestamps as strings, not numbers.
+fn ts_to_json(ts: &str) -> Value {
+    Value::String(ts.to_string())
+}
+
+fn set_by_json(&(ref value, ref creator, last_set): &(String, String, u64)) -> Value {
+    let mut object = Map::new();
+    object.insert("value".to_string(), Value::String(value.clone()));
+    object.insert("creator".to_string(), Value::String(creator.clone()));
+    object.insert("last_set".to_string(), Value::U64(last_set));
+    Value::Object(object)
+}
+
+fn object_with(name: &str, value: Value) -> Map<String, Value> {
+    let mut object = Map::new();
+    object.insert(name.to_string(), value);
+    object
+}
+
+fn is_true(value: Option<&str>) -> bool {
+    match value {
+        Some("true") | Some("1") => true,
+        _ => false,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use errors::ErrorKind;
+    use serde_types::Message;
+    use slack::Slack;
+
+    static TOKEN: &'static str = "xoxp-fake";
+
+    fn slack(fake: &FakeSlack) -> Slack {
+        Slack::with_transport(TOKEN, fake.clone())
+    }
+
+    #[test]
+    fn create_rename_and_list_channels() {
+        let fake = FakeSlack::new(TOKEN);
+        let slack = slack(&fake);
+
+        let created = slack.channels_create("testing").unwrap();
+        assert_eq!(created.name, "testing");
+        slack.channels_rename(&created.id, "renamed").unwrap();
+        slack.channels_set_topic(&created.id, "a topic").unwrap();
+
+        let channels = slack.channels_list(false).unwrap();
+        let names: Vec<&str> = channels.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["general", "renamed"]);
+        assert!(channels[0].is_general);
+    }
+
+    #[test]
+    fn answer_with_slack_error_strings() {
+        let fake = FakeSlack::new(TOKEN);
+        let slack = slack(&fake);
+        let general = fake.channel_id("general").unwrap();
+        let random = fake.add_channel("random");
+
+        match *slack.channels_create("random").unwrap_err().kind() {
+            ErrorKind::ChannelNameTaken => (),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        match *slack.channels_archive(&general).unwrap_err().kind() {
+            ErrorKind::CannotArchiveGeneralChannel => (),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        slack.channels_archive(&random).unwrap();
+        match *slack.channels_set_purpose(&random, "purpose").unwrap_err().kind() {
+            ErrorKind::ChannelIsArchived => (),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        match *Slack::with_transport("xoxp-unknown", fake.clone()).channels_info(&general).unwrap_err().kind() {
+            ErrorKind::InvalidAuthToken => (),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn record_posted_messages() {
+        let fake = FakeSlack::new(TOKEN);
+        let slack = slack(&fake);
+        let general = fake.channel_id("general").unwrap();
+
+        let message = Message { text: Some("hello".to_string()), ..Default::default() };
+        slack.chat_post_message(&general, &message).unwrap();
+        slack.chat_me_message(&general, "waves").unwrap();
+
+        let messages = fake.messages(&general);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].text, Some("hello".to_string()));
+        assert_eq!(messages[1].subtype, Some("me_message".to_string()));
+
+        let ts = messages[0].ts.parse().unwrap();
+        slack.chat_delete(&general, &ts, None).unwrap();
+        assert_eq!(fake.messages(&general).len(), 1);
+    }
+
+    #[test]
+    fn answer_with_string_timestamps() {
+        let fake = FakeSlack::new(TOKEN);
+        let slack = slack(&fake);
+        let general = fake.channel_id("general").unwrap();
+
+        let posted = slack.call_raw("chat.postMessage", &[("channel", general.as_str()), ("text", "hello")]).unwrap();
+        let ts = posted.find("ts").and_then(|ts| ts.as_str()).unwrap().to_string();
+        assert_eq!(posted.lookup("message.ts").and_then(|ts| ts.as_str()), Some(ts.as_str()));
+
+        let padded = format!("{}0", ts);
+        match *slack.call_raw("chat.delete", &[("channel", general.as_str()), ("ts", &padded)]).unwrap_err().kind() {
+            ErrorKind::MessageNotFound => (),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        slack.call_raw("chat.delete", &[("channel", general.as_str()), ("ts", &ts)]).unwrap();
+    }
+
+    #[test]
+    fn serve_over_http() {
+        let fake = FakeSlack::new(TOKEN);
+        let server = fake.serve("127.0.0.1:0").unwrap();
+        let slack = Slack::builder(TOKEN).base_url(server.base_url()).build().unwrap();
+
+        let mut arguments = HashMap::new();
+        arguments.insert("foo", "bar");
+        let response = slack.api_test(arguments, None).unwrap();
+        assert_eq!(response.args.unwrap().find("foo").and_then(|v| v.as_str()), Some("bar"));
+        assert_eq!(slack.channels_list(false).unwrap().len(), 1);
+    }
+}
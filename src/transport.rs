@@ -0,0 +1,342 @@
+// Copyright 2016 Allen A. George.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::io::Read;
+use std::str;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+use hyper::client::{pool, Client};
+#[cfg(feature = "gzip")]
+use hyper::header::{qitem, AcceptEncoding, ContentEncoding, ContentLength, Encoding};
+use hyper::header::{ContentType, Headers};
+use hyper::method::Method;
+use hyper::mime::{Attr, Mime, SubLevel, TopLevel, Value as MimeValue};
+use hyper::status::StatusCode;
+use hyper::Url;
+use serde_json::{self, Map, Value};
+use url::form_urlencoded;
+
+use auth;
+use errors::*;
+
+static SLACK_REQUEST_ID_HEADER: &'static str = "X-Slack-Req-Id";
+const MAX_LOGGED_ARGUMENT_LENGTH: usize = 64;
+
+/// An HTTP request to be made to a Slack API endpoint.
+///
+/// The `fmt::Debug` implementation redacts the access token
+/// (and other secrets) from the URL and headers.
+#[derive(Clone)]
+pub struct HttpRequest {
+    /// HTTP method with which the request is made.
+    pub method: Method,
+    /// Slack API endpoint, including any query parameters.
+    pub url: Url,
+    /// Headers to be sent with the request.
+    pub headers: Headers,
+    /// Request body, if any.
+    pub body: Option<Vec<u8>>,
+}
+
+impl HttpRequest {
+    /// Create a request with no headers and no body.
+    pub fn new(method: Method, url: Url) -> HttpRequest {
+        HttpRequest {
+            method: method,
+            url: url,
+            headers: Headers::new(),
+            body: None,
+        }
+    }
+
+    /// Describe the arguments sent in this request, with secrets
+    /// redacted and long values truncated, for logging.
+    pub fn redacted_arguments(&self) -> String {
+        let mut arguments: Vec<(String, String)> = self.url.query_pairs().into_owned().collect();
+        if let Some(ref body) = self.body {
+            match self.headers.get::<ContentType>() {
+                Some(&ContentType(Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, _))) => {
+                    arguments.extend(form_urlencoded::parse(body).into_owned());
+                }
+                Some(&ContentType(Mime(TopLevel::Application, SubLevel::Json, _))) => {
+                    let object: Map<String, Value> = serde_json::from_slice(body).unwrap_or_default();
+                    arguments.extend(object.into_iter().map(|(name, value)| {
+                        let value = match value {
+                            Value::String(s) => s,
+                            v => v.to_string(),
+                        };
+                        (name, value)
+                    }));
+                }
+                _ => arguments.push(("body".to_owned(), format!("<{} bytes>", body.len()))),
+            }
+        }
+
+        let described: Vec<String> = arguments.iter()
+            .map(|&(ref name, ref value)| {
+                let value = auth::redact(name, value);
+                if value.chars().count() > MAX_LOGGED_ARGUMENT_LENGTH {
+                    let truncated: String = value.chars().take(MAX_LOGGED_ARGUMENT_LENGTH).collect();
+                    format!("{}={}...", name, truncated)
+                } else {
+                    format!("{}={}", name, value)
+                }
+            })
+            .collect();
+        described.join(", ")
+    }
+
+    /// Create a POST request with `body` as its `application/json` body.
+    pub fn json(url: Url, body: String) -> HttpRequest {
+        let mut request = HttpRequest::new(Method::Post, url);
+        request.headers.set(ContentType(Mime(TopLevel::Application,
+                                             SubLevel::Json,
+                                             vec![(Attr::Charset, MimeValue::Utf8)])));
+        request.body = Some(body.into_bytes());
+        request
+    }
+}
+
+/// How the arguments of a Slack API method call are sent to the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestEncoding {
+    /// GET request with the arguments in the query string.
+    Query,
+    /// POST request with the arguments in an
+    /// `application/x-www-form-urlencoded` body.
+    Form,
+    /// POST request with the arguments in an `application/json` body.
+    /// Only accepted by some API methods, and only if the access token
+    /// is sent in the `Authorization` header.
+    Json,
+}
+
+impl RequestEncoding {
+    /// Create a request to `url` with `arguments` encoded as specified.
+    pub fn encode(&self, url: Url, arguments: &[(&str, &str)]) -> Result<HttpRequest> {
+        let request = match *self {
+            RequestEncoding::Query => {
+                let mut url = url;
+                if !arguments.is_empty() {
+                    url.query_pairs_mut().extend_pairs(arguments);
+                }
+                HttpRequest::new(Method::Get, url)
+            }
+            RequestEncoding::Form => {
+                let body = form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(arguments)
+                    .finish();
+                let mut request = HttpRequest::new(Method::Post, url);
+                request.headers.set(ContentType::form_url_encoded());
+                request.body = Some(body.into_bytes());
+                request
+            }
+            RequestEncoding::Json => {
+                let object: Map<String, Value> = arguments.iter()
+                    .map(|&(name, value)| (name.to_owned(), Value::String(value.to_owned())))
+                    .collect();
+                HttpRequest::json(url, try!(serde_json::to_string(&object)))
+            }
+        };
+        Ok(request)
+    }
+}
+
+impl fmt::Debug for HttpRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let headers: Vec<String> = self.headers
+            .iter()
+            .map(|h| format!("{}: {}", h.name(), auth::redact(h.name(), &h.value_string())))
+            .collect();
+        f.debug_struct("HttpRequest")
+            .field("method", &self.method)
+            .field("url", &auth::redact_url(&self.url))
+            .field("headers", &headers)
+            .field("body", &self.body.as_ref().map(|b| format!("<{} bytes>", b.len())))
+            .finish()
+    }
+}
+
+/// An HTTP response received from a Slack API endpoint.
+pub struct HttpResponse {
+    /// HTTP status code returned by the server.
+    pub status: StatusCode,
+    /// Headers returned by the server.
+    pub headers: Headers,
+    /// Response body, decompressed if it was sent compressed.
+    pub body: Box<Read + Send>,
+}
+
+impl HttpResponse {
+    /// Unique id Slack assigned to the request, if it returned one.
+    /// Include this when reporting problems to Slack.
+    pub fn request_id(&self) -> Option<&str> {
+        self.headers
+            .get_raw(SLACK_REQUEST_ID_HEADER)
+            .and_then(|values| values.first())
+            .and_then(|value| str::from_utf8(value).ok())
+    }
+}
+
+/// Mechanism via which `Slack` sends requests to, and receives
+/// responses from, the Slack Web API.
+///
+/// Implement this trait to record, mock or instrument the
+/// traffic between `Slack` and the server. `HyperTransport`
+/// is used if no other transport is specified.
+pub trait Transport: Send + Sync {
+    /// Send `request` and return the server's response.
+    ///
+    /// Implementations should *only* return an error if the
+    /// exchange itself failed; responses with non-2xx status
+    /// codes are handled by the caller.
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse>;
+}
+
+impl<T> Transport for Arc<T> where T: Transport + ?Sized {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        (**self).send(request)
+    }
+}
+
+/// Connection settings for a `HyperTransport`.
+#[derive(Clone, Debug, Default)]
+pub struct HttpConfig {
+    /// Longest time to wait for data from the server. Waits indefinitely if `None`.
+    pub read_timeout: Option<Duration>,
+    /// Longest time to wait while sending data to the server. Waits indefinitely if `None`.
+    pub write_timeout: Option<Duration>,
+    /// Maximum number of idle connections kept open to the server.
+    /// Uses `hyper`'s default if `None`. Ignored if `proxy` is set.
+    pub max_idle_connections: Option<usize>,
+    /// Host and port of an HTTP proxy through which all requests are made.
+    pub proxy: Option<(String, u16)>,
+}
+
+/// Default `Transport` that uses a `hyper` client.
+pub struct HyperTransport {
+    client: Client,
+}
+
+impl HyperTransport {
+    /// Create a transport using a `hyper::Client` with default settings.
+    pub fn new() -> HyperTransport {
+        HyperTransport::with_client(Client::new())
+    }
+
+    /// Create a transport using a `hyper::Client` configured with `config`.
+    pub fn with_config(config: &HttpConfig) -> HyperTransport {
+        let mut client = match (config.proxy.as_ref(), config.max_idle_connections) {
+            (Some(&(ref host, port)), _) => Client::with_http_proxy(host.clone(), port),
+            (None, Some(max_idle)) => Client::with_pool_config(pool::Config { max_idle: max_idle }),
+            (None, None) => Client::new(),
+        };
+        client.set_read_timeout(config.read_timeout);
+        client.set_write_timeout(config.write_timeout);
+        HyperTransport::with_client(client)
+    }
+
+    /// Create a transport using a pre-configured `hyper::Client`.
+    pub fn with_client(client: Client) -> HyperTransport {
+        HyperTransport { client: client }
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> Self {
+        HyperTransport::new()
+    }
+}
+
+impl Transport for HyperTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let mut headers = request.headers.clone();
+        accept_gzip(&mut headers);
+        let mut builder = self.client
+            .request(request.method.clone(), request.url.clone())
+            .headers(headers);
+        if let Some(ref body) = request.body {
+            builder = builder.body(&body[..]);
+        }
+
+        let response = try!(builder.send());
+        let status = response.status;
+        let mut headers = response.headers.clone();
+        let body = try!(decode_body(&mut headers, Box::new(response)));
+        Ok(HttpResponse {
+            status: status,
+            headers: headers,
+            body: body,
+        })
+    }
+}
+
+// Ask for a gzip-compressed response, unless another encoding was asked for.
+#[cfg(feature = "gzip")]
+fn accept_gzip(headers: &mut Headers) {
+    if !headers.has::<AcceptEncoding>() {
+        headers.set(AcceptEncoding(vec![qitem(Encoding::Gzip)]));
+    }
+}
+
+#[cfg(not(feature = "gzip"))]
+fn accept_gzip(_headers: &mut Headers) {}
+
+// Decompress `body` if `headers` say it's gzip-compressed, removing
+// the headers that describe the compressed body.
+#[cfg(feature = "gzip")]
+fn decode_body(headers: &mut Headers, body: Box<Read + Send>) -> Result<Box<Read + Send>> {
+    let gzipped = headers.get::<ContentEncoding>().map_or(false, |encodings| encodings.contains(&Encoding::Gzip));
+    if !gzipped {
+        return Ok(body);
+    }
+    headers.remove::<ContentEncoding>();
+    headers.remove::<ContentLength>();
+    Ok(Box::new(try!(GzDecoder::new(body))))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn decode_body(_headers: &mut Headers, body: Box<Read + Send>) -> Result<Box<Read + Send>> {
+    Ok(body)
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read, Write};
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use hyper::header::{ContentEncoding, Encoding, Headers};
+
+    #[test]
+    fn decompress_gzipped_body() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::Default);
+        encoder.write_all(br#"{"ok": true}"#).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut headers = Headers::new();
+        headers.set(ContentEncoding(vec![Encoding::Gzip]));
+        let mut body = decode_body(&mut headers, Box::new(Cursor::new(compressed))).unwrap();
+        let mut decompressed = String::new();
+        body.read_to_string(&mut decompressed).unwrap();
+
+        assert_eq!(decompressed, r#"{"ok": true}"#);
+        assert!(!headers.has::<ContentEncoding>());
+    }
+}